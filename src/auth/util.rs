@@ -1,16 +1,15 @@
 use crate::{
     files,
     google_drive::{Client, Session},
+    user,
 };
 use anyhow::{bail, Result};
-use std::path::Path;
-use std::sync::MutexGuard;
 
 
-pub fn update_for_shared_client(client: &mut MutexGuard<Client>) -> Result<()> {
-    match client.refresh_token() {
+pub async fn update_for_shared_client(client: &Client) -> Result<()> {
+    match client.refresh_token().await {
        Ok(s) => {
-           files::write_toml::<Session>(s, Path::new("~/.config/ocean-drive/session.toml").to_path_buf())?;
+           files::write_toml::<Session>(s, user::get_home()?.join(".config/ocean-drive/session.toml"))?;
            Ok(())
        },
        Err(e) => bail!("Unable to update client authorization tokens.\nTip: try to manually run `ocean-drive auth`.\nDetails: {}", e)
@@ -0,0 +1,58 @@
+/* Explains why items are (or aren't) ignored by the sync */
+
+use crate::{
+    setup,
+    sync::{ignore::IgnoreRules, pairs},
+};
+use anyhow::Result;
+use clap::{App, Arg, ArgMatches, SubCommand};
+use std::{fs, path::Path};
+
+pub fn root_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("check-ignore")
+        .about("Shows which ignore rule matches the path.")
+        .usage("ocean-drive check-ignore <paths>...")
+        .arg(
+            Arg::with_name("paths")
+                .required(true)
+                .multiple(true)
+                .help("Local paths inside of synced directories"),
+        )
+}
+
+pub fn run(m: &ArgMatches) -> Result<()> {
+    let config = setup::read_config()?;
+    let dirs = pairs::local_dirs(&config);
+
+    for p in m.values_of("paths").unwrap() {
+        let path = fs::canonicalize(p).unwrap_or_else(|_| Path::new(p).to_path_buf());
+
+        // Shared folders can be inside of the main dir, so the closest dir is the right one
+        let root = dirs
+            .iter()
+            .filter(|d| path.starts_with(d))
+            .max_by_key(|d| d.len());
+
+        let root = match root {
+            Some(r) => r,
+            None => {
+                println!("{}: not inside of synced directories", p);
+                continue;
+            }
+        };
+
+        let rules = IgnoreRules::new(root, &config.ignore)?;
+        match rules.matching_rule(&path, path.is_dir()) {
+            Some(rule) if !rule.negated => {
+                println!("{}: ignored by {:?} ({})", p, rule.pattern, rule.source)
+            }
+            Some(rule) => println!(
+                "{}: not ignored, brought back by {:?} ({})",
+                p, rule.pattern, rule.source
+            ),
+            None => println!("{}: not ignored", p),
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,109 @@
+/* Review of conflicts that wait for the user (`ask` conflict strategy) */
+
+use crate::sync::{self, conflicts::Resolution, versions::Versions};
+use anyhow::{bail, Result};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use std::fs;
+
+pub fn root_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("conflicts")
+        .about("Reviews files that were changed both locally and in the drive.")
+        .usage("ocean-drive conflicts [SUBCOMMAND (if needed)]")
+        .after_help("Without subcommands lists unresolved conflicts")
+        .subcommand(
+            SubCommand::with_name("resolve")
+                .about("Resolves conflicts by keeping one of the versions or both of them")
+                .arg(
+                    Arg::with_name("keep")
+                        .long("keep")
+                        .takes_value(true)
+                        .required(true)
+                        .possible_values(&["local", "remote", "both"])
+                        .help("Which version to keep"),
+                )
+                .arg(
+                    Arg::with_name("paths")
+                        .multiple(true)
+                        .required_unless("all")
+                        .help("Local paths of conflicting files"),
+                )
+                .arg(
+                    Arg::with_name("all")
+                        .long("all")
+                        .help("Resolve every conflict"),
+                ),
+        )
+}
+
+pub async fn run(m: &ArgMatches<'_>) -> Result<()> {
+    let ctx = sync::load_context().await?;
+    let pending = ctx.state.load()?.conflicts;
+
+    let sub = match m.subcommand() {
+        ("resolve", Some(sub)) => sub,
+        _ => {
+            if pending.is_empty() {
+                println!("No conflicts");
+                return Ok(());
+            }
+
+            println!("{} conflict(s):", pending.len());
+            for c in pending {
+                println!("  {}", c.path);
+            }
+            println!("\nRun `ocean-drive conflicts resolve --keep <local|remote|both> <paths>`");
+
+            return Ok(());
+        }
+    };
+
+    let res = match sub.value_of("keep") {
+        Some("local") => Resolution::KeepLocal,
+        Some("remote") => Resolution::KeepRemote,
+        _ => Resolution::KeepBoth,
+    };
+
+    let selected = if sub.is_present("all") {
+        pending
+    } else {
+        let mut selected = vec![];
+        for p in sub.values_of("paths").unwrap() {
+            let path = fs::canonicalize(p)
+                .map(|p| p.display().to_string())
+                .unwrap_or_else(|_| p.to_string());
+
+            match pending.iter().find(|c| c.path == path) {
+                Some(c) => selected.push(c.clone()),
+                None => bail!("No conflict for {:?}", p),
+            }
+        }
+        selected
+    };
+
+    let client = &*ctx.client;
+    let snapshot = Versions::snapshot(&ctx.versions)?;
+    let mut v_list = snapshot.clone();
+    let mut resolved = vec![];
+
+    for c in &selected {
+        if res == Resolution::KeepLocal && c.read_only {
+            eprintln!(
+                "{:?} is read-only in the drive, local version can't be kept instead of the remote one",
+                c.path
+            );
+            continue;
+        }
+
+        match ctx.resolver.apply(res, c, client, &mut v_list).await {
+            Ok(_) => resolved.push(c.id.clone()),
+            Err(e) => eprintln!("Failed to resolve conflict in {:?}: {}", c.path, e),
+        }
+    }
+
+    Versions::commit(&ctx.versions, &snapshot, v_list)?;
+    ctx.state
+        .update(|s| s.conflicts.retain(|c| !resolved.contains(&c.id)))?;
+
+    println!("{} conflict(s) resolved", resolved.len());
+    Ok(())
+}
@@ -0,0 +1,59 @@
+/* Review of deletions held by the mass-deletion safeguard */
+
+use crate::sync::{
+    self,
+    deletions::{self, Side},
+};
+use anyhow::Result;
+use clap::{App, ArgMatches, SubCommand};
+
+pub fn root_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("deletions")
+        .about("Reviews deletions that were paused because too many files were deleted at once.")
+        .usage("ocean-drive deletions [SUBCOMMAND (if needed)]")
+        .after_help("Without subcommands lists pending deletions")
+        .subcommand(SubCommand::with_name("confirm").about("Applies every pending deletion"))
+        .subcommand(
+            SubCommand::with_name("discard").about(
+                "Drops pending deletions. Deleted files are brought back from the other side",
+            ),
+        )
+}
+
+pub async fn run(m: &ArgMatches<'_>) -> Result<()> {
+    let ctx = sync::load_context().await?;
+    let confirm = match m.subcommand_name() {
+        Some("confirm") => true,
+        Some("discard") => false,
+        _ => {
+            let pending = ctx.state.load()?.pending_deletions;
+            if pending.is_empty() {
+                println!("No deletions pending");
+                return Ok(());
+            }
+
+            println!("{} deletions pending:", pending.len());
+            for d in pending {
+                let place = match d.side {
+                    Side::Local => "deleted locally",
+                    Side::Remote => "trashed in the drive",
+                };
+                println!("  {} ({})", d.version.path, place);
+            }
+            println!("\nRun `ocean-drive deletions confirm` or `ocean-drive deletions discard`");
+
+            return Ok(());
+        }
+    };
+
+    let client = &*ctx.client;
+    let resolved =
+        deletions::resolve(confirm, &ctx.config, client, &ctx.state, &ctx.versions).await?;
+
+    println!(
+        "{} deletion(s) {}",
+        resolved,
+        if confirm { "confirmed" } else { "discarded" }
+    );
+    Ok(())
+}
@@ -5,7 +5,7 @@ use std::{fs, io::prelude::*, path::PathBuf};
 use toml;
 
 pub fn read_bytes(path: PathBuf) -> Result<Vec<u8>> {
-    let f = fs::OpenOptions::new().write(true).read(true).open(path)?;
+    let f = fs::OpenOptions::new().read(true).open(path)?;
     let mut reader = BufReader::new(f);
     let mut buffer = Vec::new();
 
@@ -1,10 +1,17 @@
 pub mod errors;
+pub mod throttle;
 pub mod types;
 use anyhow::{bail, Result};
 use errors::DriveError;
-use reqwest::blocking::Client as HttpClient;
+use reqwest::{Body, Client as HttpClient, RequestBuilder, Response};
 use serde::{Deserialize, Serialize};
-use types::{File, FileList, FileUploadBody};
+use std::{
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+use throttle::Throttle;
+use tokio::sync::Mutex;
+use types::{File, FileAttributes, FileList, FileUploadBody};
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Session {
@@ -17,12 +24,25 @@ pub struct Config {
     pub dir: String,
 }
 
-#[derive(Clone)]
+/// Called with the count of bytes transferred so far, while the content is sent or fetched
+pub type Progress = Arc<dyn Fn(u64) + Send + Sync>;
+
+/// Token refreshed this recently is not refreshed again. Requests that failed with the old token
+/// at the same time only need to be retried
+const REFRESH_COOLDOWN: Duration = Duration::from_secs(60);
+/// Default timeout of requests to the API
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Client is shared between tasks, the session is replaced for all of them when it's refreshed
 pub struct Client {
     client_id: String,
     client_secret: String,
     redirect_uri: String,
-    auth: Option<Session>,
+    auth: RwLock<Option<Session>>,
+    /// Time of the last refresh, it's locked while the token is refreshed
+    refreshed_at: Mutex<Option<Instant>>,
+    upload_limit: Arc<Throttle>,
+    download_limit: Arc<Throttle>,
     http: HttpClient,
 }
 
@@ -33,11 +53,37 @@ impl Client {
             client_id,
             client_secret,
             redirect_uri,
-            auth: None,
-            http: HttpClient::new(),
+            auth: RwLock::new(None),
+            refreshed_at: Mutex::new(None),
+            upload_limit: Arc::new(Throttle::new()),
+            download_limit: Arc::new(Throttle::new()),
+            http: HttpClient::builder()
+                .timeout(REQUEST_TIMEOUT)
+                .build()
+                .expect("Failed to build the HTTP client"),
         }
     }
 
+    /// Limits rates of contents that are sent and fetched, in bytes per second. `None` means no
+    /// limit
+    pub fn set_limits(&self, upload: Option<u64>, download: Option<u64>) {
+        self.upload_limit.set_rate(upload);
+        self.download_limit.set_rate(download);
+    }
+
+    /// Content is sent before the response comes, so the timeout covers all of it at the
+    /// limited rate
+    fn upload(&self, contents: Vec<u8>, progress: Option<Progress>) -> (Body, Duration) {
+        let len = contents.len() as u64;
+        let secs = self.upload_limit.rate().map_or(0, |r| len / r);
+        let stream = throttle::throttled(contents, Arc::clone(&self.upload_limit), progress);
+
+        (
+            Body::wrap_stream(stream),
+            REQUEST_TIMEOUT + Duration::from_secs(secs),
+        )
+    }
+
     pub fn get_user_authorization_url(&self, scope: &str, redirect_uri: &str) -> String {
         format!(
             "https://accounts.google.com/o/oauth2/v2/auth?client_id={}&response_type=code&redirect_uri={}&scope={}&access_type=offline",
@@ -47,12 +93,16 @@ impl Client {
         )
     }
 
-    pub fn set_session(&mut self, s: Session) {
-        self.auth = Some(s);
+    pub fn set_session(&self, s: Session) {
+        *self.auth.write().unwrap_or_else(|e| e.into_inner()) = Some(s);
     }
 
-    fn get(&self, url: String, query: &[(&str, &str)]) -> Result<reqwest::blocking::Response> {
-        if let Some(auth) = &self.auth {
+    fn session(&self) -> Option<Session> {
+        self.auth.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    async fn get(&self, url: String, query: &[(&str, &str)]) -> Result<Response> {
+        if let Some(auth) = self.session() {
             match self
                 .http
                 .get(&url)
@@ -60,6 +110,7 @@ impl Client {
                 .header("Content-Type", "application/json")
                 .query(query)
                 .send()
+                .await
             {
                 Ok(resp) => {
                     if resp.status() == 401 {
@@ -74,16 +125,16 @@ impl Client {
         bail!(DriveError::Unauthorized);
     }
 
-    fn get_json<T>(&self, url: String, query: &[(&str, &str)]) -> Result<T>
+    async fn get_json<T>(&self, url: String, query: &[(&str, &str)]) -> Result<T>
     where
         T: serde::de::DeserializeOwned,
     {
-        match self.get(url, query) {
+        match self.get(url, query).await {
             Ok(resp) => {
                 if resp.status() == 404 {
                     bail!(DriveError::NotFound);
                 }
-                match resp.json::<T>() {
+                match resp.json::<T>().await {
                     Ok(data) => Ok(data),
                     Err(e) => {
                         bail!("Failed to desirialize JSON data.\nError: {}", e);
@@ -94,7 +145,7 @@ impl Client {
         }
     }
 
-    fn get_token(
+    async fn get_token(
         &self,
         refresh: bool,
         auth_code: Option<String>,
@@ -119,13 +170,14 @@ impl Client {
             .post("https://oauth2.googleapis.com/token")
             .form(&params)
             .send()
+            .await
         {
             Ok(resp) => {
                 if resp.status() == 401 {
                     bail!(DriveError::Unauthorized);
                 }
 
-                match resp.json::<Session>() {
+                match resp.json::<Session>().await {
                     Ok(session) => Ok(session),
                     Err(e) => {
                         bail!("Failed to deserialize auth data.\nDetails: {}", e);
@@ -138,20 +190,31 @@ impl Client {
         }
     }
 
-    pub fn authorize_with_code(&mut self, code: String) -> Result<Session> {
-        let session = self.get_token(false, Some(code.clone()), None)?;
+    pub async fn authorize_with_code(&self, code: String) -> Result<Session> {
+        let session = self.get_token(false, Some(code.clone()), None).await?;
         self.set_session(session.clone());
 
         Ok(session)
     }
 
-    pub fn refresh_token(&mut self) -> Result<Session> {
-        if let Some(auth) = &self.auth {
+    /// Gets a new access token. Tasks that fail with the old token at once wait while the first
+    /// one refreshes it, and then get its session
+    pub async fn refresh_token(&self) -> Result<Session> {
+        let mut refreshed_at = self.refreshed_at.lock().await;
+
+        if let Some(auth) = self.session() {
+            if refreshed_at.is_some_and(|at| at.elapsed() < REFRESH_COOLDOWN) {
+                return Ok(auth);
+            }
+
             if let Some(refresh_token) = &auth.refresh_token {
-                let mut new_session = self.get_token(true, None, Some(refresh_token.clone()))?;
+                let mut new_session = self
+                    .get_token(true, None, Some(refresh_token.clone()))
+                    .await?;
 
                 new_session.refresh_token = Some(String::from(refresh_token));
                 self.set_session(new_session.clone());
+                *refreshed_at = Some(Instant::now());
 
                 return Ok(new_session);
             }
@@ -163,7 +226,7 @@ impl Client {
     /// Performs a GET Request to /files route, listing all files that meet `query` parameter
     /// - query is empty by default
     /// - fields are the list of all fields that are present in `File` struct
-    pub fn list_files(&self, query: Option<&str>, fields: Option<&str>) -> Result<FileList> {
+    pub async fn list_files(&self, query: Option<&str>, fields: Option<&str>) -> Result<FileList> {
         self.get_json::<FileList>(
             "https://www.googleapis.com/drive/v3/files".to_string(),
             &[
@@ -171,18 +234,25 @@ impl Client {
                 (
                     "fields",
                     fields.unwrap_or(
-                        "files(id, md5Checksum, name, trashed, mimeType, parents, version)",
+                        "files(id, md5Checksum, name, trashed, mimeType, parents, version, size, modifiedTime, createdTime, appProperties, capabilities(canEdit), shortcutDetails)",
                     ),
                 ),
             ],
         )
+        .await
     }
 
-    pub fn get_file(&self, id: &str) -> Result<Option<File>> {
-        match self.get_json(
-            format!("https://www.googleapis.com/drive/v3/files/{}", id),
-            &[("fields", "id, name, mimeType, parents, version")],
-        ) {
+    pub async fn get_file(&self, id: &str) -> Result<Option<File>> {
+        match self
+            .get_json(
+                format!("https://www.googleapis.com/drive/v3/files/{}", id),
+                &[(
+                    "fields",
+                    "id, name, md5Checksum, trashed, mimeType, parents, version, size, modifiedTime, createdTime, appProperties, capabilities(canEdit)",
+                )],
+            )
+            .await
+        {
             Ok(f) => Ok(Some(f)),
             Err(e) => {
                 if let Some(drive_err) = e.downcast_ref::<DriveError>() {
@@ -196,16 +266,31 @@ impl Client {
         }
     }
 
+    /// Lists folders from the "Shared with me" section of the drive
+    pub async fn list_shared_folders(&self) -> Result<FileList> {
+        self.list_files(
+            Some("sharedWithMe = true and mimeType = 'application/vnd.google-apps.folder' and trashed = false"),
+            Some("files(id, name, mimeType, capabilities(canEdit))"),
+        )
+        .await
+    }
+
     /// Kind of a shortcut to `list_files` when getting the first file with some name (if it has duplicates)
-    pub fn get_file_by_name(&self, name: &str, parent_id: Option<String>) -> Result<Option<File>> {
-        let list = self.list_files(
-            Some(&format!(
-                "name = '{}' and '{}' in parents",
-                &name,
-                &parent_id.unwrap_or(String::from(""))
-            )),
-            None,
-        )?;
+    pub async fn get_file_by_name(
+        &self,
+        name: &str,
+        parent_id: Option<String>,
+    ) -> Result<Option<File>> {
+        let list = self
+            .list_files(
+                Some(&format!(
+                    "name = '{}' and '{}' in parents",
+                    &name,
+                    &parent_id.unwrap_or(String::from(""))
+                )),
+                None,
+            )
+            .await?;
 
         if list.files.len() == 0 {
             return Ok(None);
@@ -214,22 +299,79 @@ impl Client {
         Ok(Some(list.files[0].clone()))
     }
 
-    pub fn download_file(&self, id: &str) -> Result<Vec<u8>> {
-        match self.get(
-            format!("https://www.googleapis.com/drive/v3/files/{}", id),
-            &[("alt", "media")],
-        ) {
-            Ok(resp) => Ok(resp.bytes().unwrap().into_iter().collect::<Vec<u8>>()),
+    pub async fn download_file(&self, id: &str) -> Result<Vec<u8>> {
+        self.download(id, None).await
+    }
+
+    pub async fn download_with_progress(&self, id: &str, progress: Progress) -> Result<Vec<u8>> {
+        self.download(id, Some(progress)).await
+    }
+
+    /// Content comes in chunks, so the timeout is only for the response to start
+    async fn download(&self, id: &str, progress: Option<Progress>) -> Result<Vec<u8>> {
+        match self
+            .get(
+                format!("https://www.googleapis.com/drive/v3/files/{}", id),
+                &[("alt", "media")],
+            )
+            .await
+        {
+            Ok(resp) => throttle::read_throttled(resp, &self.download_limit, progress).await,
             Err(e) => Err(e),
         }
     }
 
-    pub fn create_dir(&self, name: &str, parent_id: String) -> Result<File> {
-        if let Some(auth) = &self.auth {
+    /// Downloads `len` bytes of the content starting from `offset`. Returns less bytes (or none) if
+    /// the file ends earlier
+    #[cfg(feature = "mount")]
+    pub async fn download_range(&self, id: &str, offset: u64, len: u64) -> Result<Vec<u8>> {
+        if len == 0 {
+            return Ok(vec![]);
+        }
+
+        if let Some(auth) = self.session() {
+            let url = format!("https://www.googleapis.com/drive/v3/files/{}", id);
+            let resp = match self
+                .http
+                .get(&url)
+                .bearer_auth(auth.access_token.clone())
+                .header("Range", format!("bytes={}-{}", offset, offset + len - 1))
+                .query(&[("alt", "media")])
+                .send()
+                .await
+            {
+                Ok(resp) => resp,
+                Err(_) => bail!("Request failed (GET {})", url),
+            };
+
+            // Anything but the requested part would be cached as the content of the file
+            return match resp.status().as_u16() {
+                206 => Ok(resp.bytes().await?.to_vec()),
+                // Range is ignored, so the part is cut from the whole content
+                200 => {
+                    let contents = resp.bytes().await?;
+                    let start = contents.len().min(offset as usize);
+                    let end = contents.len().min((offset + len) as usize);
+                    Ok(contents[start..end].to_vec())
+                }
+                401 => bail!(DriveError::Unauthorized),
+                404 => bail!(DriveError::NotFound),
+                // Range starts after the end of the file
+                416 => Ok(vec![]),
+                status => bail!("Unable to download a part of the file (status {})", status),
+            };
+        }
+
+        bail!(DriveError::Unauthorized);
+    }
+
+    pub async fn create_dir(&self, name: &str, parent_id: String) -> Result<File> {
+        if let Some(auth) = self.session() {
             let body = FileUploadBody {
                 name: name.to_string(),
                 parents: vec![parent_id],
                 mime_type: Some("application/vnd.google-apps.folder".to_string()),
+                attributes: FileAttributes::default(),
             };
 
             // Initialize uploading with sending first request in the sequence
@@ -240,95 +382,193 @@ impl Client {
                 .bearer_auth(auth.access_token.clone())
                 .header("Content-Type", "application/json")
                 .body(serde_json::to_string(&body).unwrap())
-                .send()?;
+                .send()
+                .await?;
 
             if res.status() == 401 {
                 bail!(DriveError::Unauthorized);
             }
 
-            return Ok(res.json::<File>()?);
+            return Ok(res.json::<File>().await?);
         }
 
         bail!(DriveError::Unauthorized);
     }
 
-    pub fn upload_file(&self, name: &str, parent_id: String, contents: Vec<u8>) -> Result<File> {
-        if let Some(auth) = &self.auth {
+    pub async fn upload_with_progress(
+        &self,
+        name: &str,
+        parent_id: String,
+        contents: Vec<u8>,
+        attributes: FileAttributes,
+        progress: Progress,
+    ) -> Result<File> {
+        self.create(name, parent_id, contents, attributes, Some(progress))
+            .await
+    }
+
+    async fn create(
+        &self,
+        name: &str,
+        parent_id: String,
+        contents: Vec<u8>,
+        attributes: FileAttributes,
+        progress: Option<Progress>,
+    ) -> Result<File> {
+        if let Some(auth) = self.session() {
             let body = FileUploadBody {
                 name: name.to_string(),
                 parents: vec![parent_id],
                 mime_type: None,
+                attributes,
             };
 
-            // Initialize uploading with sending first request in the sequence
-            let res = self
+            let start = self
                 .http
                 .post("https://www.googleapis.com/upload/drive/v3/files")
-                .bearer_auth(auth.access_token.clone())
-                .header("Content-Type", "application/json")
-                .query(&[("uploadType", "resumable"), ("fields", "*")])
-                .body(serde_json::to_string(&body).unwrap())
-                .send()?;
+                .body(serde_json::to_string(&body).unwrap());
 
-            if res.status() == 401 {
-                bail!(DriveError::Unauthorized);
-            }
+            return self
+                .upload_resumable(&auth, start, contents, progress)
+                .await;
+        }
 
-            let upload_location = res.headers().get("Location");
+        bail!(DriveError::Unauthorized);
+    }
 
-            if upload_location.is_none() {
-                bail!("Unable to create resumable session to upload file to the cloud");
-            }
-            let upload_location = upload_location.unwrap();
+    pub async fn update_file(
+        &self,
+        id: String,
+        contents: Vec<u8>,
+        attributes: FileAttributes,
+    ) -> Result<File> {
+        self.update(id, contents, attributes, None).await
+    }
 
-            // Upload at once, at the end we should get all file data
-            let created = self
-                .http
-                .put(upload_location.to_str().unwrap())
-                .bearer_auth(auth.access_token.clone())
-                .body(contents)
-                .send()?;
+    pub async fn update_with_progress(
+        &self,
+        id: String,
+        contents: Vec<u8>,
+        attributes: FileAttributes,
+        progress: Progress,
+    ) -> Result<File> {
+        self.update(id, contents, attributes, Some(progress)).await
+    }
 
-            if created.status() != 200 && created.status() != 201 {
-                bail!("File wasn't uploaded successfully");
-            }
+    /// Content is sent in a resumable session, so new attributes are saved with it
+    async fn update(
+        &self,
+        id: String,
+        contents: Vec<u8>,
+        attributes: FileAttributes,
+        progress: Option<Progress>,
+    ) -> Result<File> {
+        if let Some(auth) = self.session() {
+            // Creation time can't be changed after the file is created
+            let attributes = FileAttributes {
+                created_time: None,
+                ..attributes
+            };
+
+            let start = self
+                .http
+                .patch(format!(
+                    "https://www.googleapis.com/upload/drive/v3/files/{}",
+                    id
+                ))
+                .body(serde_json::to_string(&attributes).unwrap());
 
-            // TODO: Handle errors with JSON Deserialization
-            return Ok(created.json::<File>()?);
+            return self
+                .upload_resumable(&auth, start, contents, progress)
+                .await;
         }
 
         bail!(DriveError::Unauthorized);
     }
 
-    pub fn update_file(&self, id: String, contents: Vec<u8>) -> Result<File> {
-        if let Some(auth) = &self.auth {
+    /// Saves attributes of the file without sending the content again
+    pub async fn update_attributes(&self, id: String, attributes: FileAttributes) -> Result<File> {
+        let attributes = FileAttributes {
+            created_time: None,
+            ..attributes
+        };
+
+        if let Some(auth) = self.session() {
             let res = self
                 .http
-                .patch(format!(
-                    "https://www.googleapis.com/upload/drive/v3/files/{}",
-                    id
-                ))
+                .patch(format!("https://www.googleapis.com/drive/v3/files/{}", id))
+                .header("Content-Type", "application/json")
                 .bearer_auth(auth.access_token.clone())
-                .query(&[("uploadType", "media")])
-                .body(contents)
-                .send()?;
+                .query(&[("fields", "*")])
+                .body(serde_json::to_string(&attributes).unwrap())
+                .send()
+                .await?;
 
             if res.status() == 401 {
                 bail!(DriveError::Unauthorized);
             }
+            if res.status() == 404 {
+                bail!(DriveError::NotFound);
+            }
 
-            return Ok(res.json::<File>()?);
+            return Ok(res.json::<File>().await?);
         }
 
         bail!(DriveError::Unauthorized);
     }
 
+    /// Sends the metadata in `start` to open a resumable session and uploads the content to it
+    async fn upload_resumable(
+        &self,
+        auth: &Session,
+        start: RequestBuilder,
+        contents: Vec<u8>,
+        progress: Option<Progress>,
+    ) -> Result<File> {
+        // Initialize uploading with sending first request in the sequence
+        let res = start
+            .bearer_auth(auth.access_token.clone())
+            .header("Content-Type", "application/json")
+            .query(&[("uploadType", "resumable"), ("fields", "*")])
+            .send()
+            .await?;
+
+        match res.status().as_u16() {
+            401 => bail!(DriveError::Unauthorized),
+            404 => bail!(DriveError::NotFound),
+            _ => {}
+        }
+
+        let upload_location = match res.headers().get("Location") {
+            Some(l) => l.to_str()?.to_string(),
+            None => bail!("Unable to create resumable session to upload file to the cloud"),
+        };
+
+        // Upload at once, at the end we should get all file data
+        let (body, timeout) = self.upload(contents, progress);
+        let uploaded = self
+            .http
+            .put(upload_location)
+            .bearer_auth(auth.access_token.clone())
+            .timeout(timeout)
+            .body(body)
+            .send()
+            .await?;
+
+        if uploaded.status() != 200 && uploaded.status() != 201 {
+            bail!("File wasn't uploaded successfully");
+        }
+
+        // TODO: Handle errors with JSON Deserialization
+        Ok(uploaded.json::<File>().await?)
+    }
+
     /// Rename also could mean moving of the file, so the parent should be specified
-    pub fn rename_file(&self, id: String, new_name: &str, parent_id: String) -> Result<File> {
+    pub async fn rename_file(&self, id: String, new_name: &str, parent_id: String) -> Result<File> {
         let mut body = std::collections::HashMap::new();
         body.insert("name", new_name);
 
-        if let Some(auth) = &self.auth {
+        if let Some(auth) = self.session() {
             let res = self
                 .http
                 .patch(format!("https://www.googleapis.com/drive/v3/files/{}", id))
@@ -336,25 +576,65 @@ impl Client {
                 .bearer_auth(auth.access_token.clone())
                 .query(&[("fields", "*"), ("addParents", &parent_id)])
                 .body(serde_json::to_string(&body).unwrap())
-                .send()?;
+                .send()
+                .await?;
 
             if res.status() == 401 {
                 bail!(DriveError::Unauthorized);
             }
 
-            return Ok(res.json::<File>()?);
+            return Ok(res.json::<File>().await?);
         }
 
         bail!(DriveError::Unauthorized);
     }
 
-    pub fn detele_file(&self, id: String) -> Result<()> {
-        if let Some(auth) = &self.auth {
+    /// Moves the file to the trash of the drive, it can be restored from there later
+    pub async fn trash_file(&self, id: String) -> Result<File> {
+        self.set_trashed(id, true).await
+    }
+
+    pub async fn untrash_file(&self, id: String) -> Result<File> {
+        self.set_trashed(id, false).await
+    }
+
+    async fn set_trashed(&self, id: String, trashed: bool) -> Result<File> {
+        let mut body = std::collections::HashMap::new();
+        body.insert("trashed", trashed);
+
+        if let Some(auth) = self.session() {
+            let res = self
+                .http
+                .patch(format!("https://www.googleapis.com/drive/v3/files/{}", id))
+                .header("Content-Type", "application/json")
+                .bearer_auth(auth.access_token.clone())
+                .query(&[("fields", "*")])
+                .body(serde_json::to_string(&body).unwrap())
+                .send()
+                .await?;
+
+            if res.status() == 401 {
+                bail!(DriveError::Unauthorized);
+            }
+            if res.status() == 404 {
+                bail!(DriveError::NotFound);
+            }
+
+            return Ok(res.json::<File>().await?);
+        }
+
+        bail!(DriveError::Unauthorized);
+    }
+
+    /// Deletes the file permanently, skipping the trash
+    pub async fn detele_file(&self, id: String) -> Result<()> {
+        if let Some(auth) = self.session() {
             let res = self
                 .http
                 .delete(format!("https://www.googleapis.com/drive/v3/files/{}", id))
                 .bearer_auth(auth.access_token.clone())
-                .send()?;
+                .send()
+                .await?;
 
             if res.status() == 401 {
                 bail!(DriveError::Unauthorized);
@@ -0,0 +1,129 @@
+/*
+    Token bucket that limits the rate of transfers. One bucket is shared by every transfer in the
+    same direction, so the limit is for all of them together. The rate can be changed at any time,
+    transfers that are running pick it up with the next chunk.
+    Throttled streams also report their progress.
+*/
+use crate::google_drive::Progress;
+use anyhow::Result;
+use bytes::Bytes;
+use futures::{stream, Stream};
+use reqwest::Response;
+use std::{
+    io,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Max size of the chunk sent or read at once, smaller chunks make the rate smoother
+const CHUNK: usize = 16 * 1024;
+
+pub struct Throttle {
+    bucket: Mutex<Bucket>,
+}
+
+struct Bucket {
+    /// Bytes per second, `None` means no limit
+    rate: Option<u64>,
+    /// Bytes that can be sent right away. Goes below zero when transfers have to wait
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl Throttle {
+    pub fn new() -> Self {
+        Self {
+            bucket: Mutex::new(Bucket {
+                rate: None,
+                tokens: 0.0,
+                refilled: Instant::now(),
+            }),
+        }
+    }
+
+    pub fn rate(&self) -> Option<u64> {
+        self.bucket.lock().unwrap_or_else(|e| e.into_inner()).rate
+    }
+
+    pub fn set_rate(&self, rate: Option<u64>) {
+        let rate = rate.filter(|r| *r > 0);
+        let mut bucket = self.bucket.lock().unwrap_or_else(|e| e.into_inner());
+        if bucket.rate != rate {
+            bucket.rate = rate;
+            bucket.tokens = 0.0;
+            bucket.refilled = Instant::now();
+        }
+    }
+
+    /// Takes `n` bytes from the bucket, waiting until they are refilled if needed
+    pub async fn take(&self, n: usize) {
+        if let Some(wait) = self.reserve(n) {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Takes `n` bytes right away. Returns how long the transfer should wait for them
+    fn reserve(&self, n: usize) -> Option<Duration> {
+        let mut bucket = self.bucket.lock().unwrap_or_else(|e| e.into_inner());
+        let rate = bucket.rate? as f64;
+
+        // Bucket holds a second of transfer at most, so idle time doesn't allow bursts
+        let elapsed = bucket.refilled.elapsed().as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(rate) - n as f64;
+        bucket.refilled = Instant::now();
+
+        if bucket.tokens >= 0.0 {
+            return None;
+        }
+        Some(Duration::from_secs_f64(-bucket.tokens / rate))
+    }
+}
+
+/// Stream of `contents` split into chunks, every chunk is taken from the throttle before it's
+/// sent
+pub fn throttled(
+    contents: Vec<u8>,
+    throttle: Arc<Throttle>,
+    progress: Option<Progress>,
+) -> impl Stream<Item = io::Result<Bytes>> {
+    let contents = Bytes::from(contents);
+
+    stream::unfold(0, move |done| {
+        let (contents, throttle, progress) =
+            (contents.clone(), Arc::clone(&throttle), progress.clone());
+
+        async move {
+            if done >= contents.len() {
+                return None;
+            }
+
+            let end = contents.len().min(done + CHUNK);
+            throttle.take(end - done).await;
+            if let Some(progress) = &progress {
+                progress(end as u64);
+            }
+
+            Some((Ok(contents.slice(done..end)), end))
+        }
+    })
+}
+
+/// Reads the whole body of the response, taking every chunk from the throttle
+pub async fn read_throttled(
+    mut resp: Response,
+    throttle: &Throttle,
+    progress: Option<Progress>,
+) -> Result<Vec<u8>> {
+    let mut contents = vec![];
+
+    while let Some(chunk) = resp.chunk().await? {
+        throttle.take(chunk.len()).await;
+        contents.extend_from_slice(&chunk);
+
+        if let Some(progress) = &progress {
+            progress(contents.len() as u64);
+        }
+    }
+
+    Ok(contents)
+}
@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct FileList {
@@ -10,8 +11,23 @@ pub struct FileUploadBody {
     pub name: String,
     pub parents: Vec<String>,
     #[serde(rename = "mimeType")]
-    pub mime_type: Option<String>
-    // TODO: Add createdAt field (will point to time when file was created LOCALLY)
+    pub mime_type: Option<String>,
+    #[serde(flatten)]
+    pub attributes: FileAttributes,
+}
+
+/// Attributes of the local file that are kept in the drive along with the content
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct FileAttributes {
+    /// RFC 3339, so the drive shows when the file was changed and not when it was uploaded. The
+    /// drive sets current time when times are missing
+    #[serde(rename = "modifiedTime", skip_serializing_if = "Option::is_none")]
+    pub modified_time: Option<String>,
+    #[serde(rename = "createdTime", skip_serializing_if = "Option::is_none")]
+    pub created_time: Option<String>,
+    /// Private to the app: permissions and the owner of the file
+    #[serde(rename = "appProperties", skip_serializing_if = "Option::is_none")]
+    pub app_properties: Option<HashMap<String, String>>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -24,5 +40,56 @@ pub struct File {
     #[serde(rename = "md5Checksum")]
     pub md5: Option<String>,
     pub version: Option<String>,
+    /// Size of the content in bytes. Google Docs files have no size
+    pub size: Option<String>,
     pub parents: Option<Vec<String>>,
+    /// RFC 3339 time of the last change
+    #[serde(rename = "modifiedTime")]
+    pub modified_time: Option<String>,
+    #[serde(rename = "createdTime")]
+    pub created_time: Option<String>,
+    /// Properties visible only to the app, e.g. permissions of the local file
+    #[serde(rename = "appProperties")]
+    pub app_properties: Option<HashMap<String, String>>,
+    pub capabilities: Option<Capabilities>,
+    #[serde(rename = "shortcutDetails")]
+    pub shortcut_details: Option<ShortcutDetails>,
+}
+
+/// What current user is allowed to do with the file
+#[derive(Deserialize, Debug, Clone)]
+pub struct Capabilities {
+    #[serde(rename = "canEdit")]
+    pub can_edit: Option<bool>,
+}
+
+/// Only present for files with `application/vnd.google-apps.shortcut` mime type
+#[derive(Deserialize, Debug, Clone)]
+pub struct ShortcutDetails {
+    #[serde(rename = "targetId")]
+    pub target_id: String,
+    #[serde(rename = "targetMimeType")]
+    pub target_mime_type: Option<String>,
+}
+
+impl File {
+    /// Permission bits saved by the app, like `0o755`
+    pub fn mode(&self) -> Option<u32> {
+        self.app_property("mode")
+            .and_then(|m| u32::from_str_radix(m, 8).ok())
+    }
+
+    /// Owner (uid, gid) saved by the app
+    pub fn owner(&self) -> Option<(u32, u32)> {
+        let uid = self.app_property("uid")?.parse().ok()?;
+        let gid = self.app_property("gid")?.parse().ok()?;
+        Some((uid, gid))
+    }
+
+    fn app_property(&self, key: &str) -> Option<&str> {
+        self.app_properties
+            .as_ref()
+            .and_then(|p| p.get(key))
+            .map(String::as_str)
+    }
 }
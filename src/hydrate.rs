@@ -0,0 +1,156 @@
+/* Fetching and evicting content of online-only files */
+
+use crate::{
+    files,
+    google_drive::Client,
+    sync::{
+        self, pairs,
+        placeholders::{self, Placeholder},
+        util,
+        versions::{Versions, VersionsList},
+    },
+};
+use anyhow::{bail, Result};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+pub fn root_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("hydrate")
+        .about("Downloads content of online-only files.")
+        .usage("ocean-drive hydrate <paths>...")
+        .arg(paths_arg())
+}
+
+pub fn dehydrate_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("dehydrate")
+        .about("Replaces synced files with online-only placeholders to free up space.")
+        .usage("ocean-drive dehydrate <paths>...")
+        .after_help("Files with changes that are not uploaded yet and pinned files are kept")
+        .arg(paths_arg())
+}
+
+fn paths_arg() -> Arg<'static, 'static> {
+    Arg::with_name("paths")
+        .required(true)
+        .multiple(true)
+        .help("Files or directories (everything inside of them is processed)")
+}
+
+/// Fetches content when `hydrate` is true, otherwise evicts it
+pub async fn run(m: &ArgMatches<'_>, hydrate: bool) -> Result<()> {
+    let ctx = sync::load_context().await?;
+    let client = &*ctx.client;
+    let v_list = util::lock(&ctx.versions).list()?;
+    let dirs = pairs::local_dirs(&ctx.config);
+
+    let mut count = 0;
+    for p in m.values_of("paths").unwrap() {
+        let path = fs::canonicalize(p)?;
+        // Shared folders can be inside of the main dir, so the closest dir is the right one
+        let root = dirs
+            .iter()
+            .filter(|d| path.starts_with(d))
+            .max_by_key(|d| d.len());
+        let root = match root {
+            Some(r) => r,
+            None => bail!("{:?} is not inside of synced directories", p),
+        };
+
+        for f in walk(Path::new(root), &path)? {
+            let res = if hydrate {
+                hydrate_file(&f, client, &v_list).await
+            } else {
+                let rel = f.strip_prefix(root)?;
+                if ctx.config.placeholders.is_pinned(rel) {
+                    println!("Info: {:?} is pinned, skipping it", f.display());
+                    continue;
+                }
+                dehydrate_file(&f, &v_list)
+            };
+
+            match res {
+                Ok(true) => count += 1,
+                Ok(false) => {}
+                Err(e) => eprintln!("Failed to process {:?}: {}", f.display(), e),
+            }
+        }
+    }
+
+    println!(
+        "{} file(s) {}",
+        count,
+        if hydrate { "hydrated" } else { "dehydrated" }
+    );
+    Ok(())
+}
+
+/// Lists files inside of the path, skipping files the app keeps for itself
+fn walk(root: &Path, p: &Path) -> Result<Vec<PathBuf>> {
+    if util::is_internal(root, p) {
+        return Ok(vec![]);
+    }
+    if !p.is_dir() {
+        return Ok(vec![p.to_path_buf()]);
+    }
+
+    let mut res = vec![];
+    for entry in fs::read_dir(p)? {
+        res.extend(walk(root, &entry?.path())?);
+    }
+    Ok(res)
+}
+
+async fn hydrate_file(f: &Path, client: &Client, v_list: &VersionsList) -> Result<bool> {
+    let placeholder = match placeholders::read(f) {
+        Some(p) => p,
+        None => return Ok(false),
+    };
+
+    let content = client.download_file(&placeholder.id).await?;
+    let read_only = Versions::find_item_by_path(f.to_path_buf(), v_list)
+        .map(|v| v.1.read_only)
+        .unwrap_or(false);
+
+    util::set_readonly(f, false)?;
+    fs::write(f, content)?;
+    util::set_readonly(f, read_only)?;
+
+    Ok(true)
+}
+
+fn dehydrate_file(f: &Path, v_list: &VersionsList) -> Result<bool> {
+    if placeholders::read(f).is_some() {
+        return Ok(false);
+    }
+
+    let (id, v) = match Versions::find_item_by_path(f.to_path_buf(), v_list) {
+        Some(v) if v.1.shortcut.is_none() => v,
+        _ => {
+            println!("Info: {:?} is not synced yet, skipping it", f.display());
+            return Ok(false);
+        }
+    };
+
+    let content = files::read_bytes(f.to_path_buf())?;
+    let hash = format!("{:x}", md5::compute(&content));
+    if v.md5.as_ref() != Some(&hash) {
+        println!(
+            "Info: {:?} has changes that are not uploaded yet, skipping it",
+            f.display()
+        );
+        return Ok(false);
+    }
+
+    placeholders::write(
+        f,
+        &Placeholder {
+            id,
+            size: content.len() as u64,
+            md5: v.md5,
+        },
+    )?;
+    Ok(true)
+}
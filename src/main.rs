@@ -1,11 +1,25 @@
 mod auth;
+mod check_ignore;
+mod conflicts;
+mod deletions;
 mod files;
 mod google_drive;
+mod hydrate;
+#[cfg(feature = "mount")]
+mod mount;
+mod oneshot;
 mod parse_url;
+mod queue;
 mod readline;
 mod redirect_listener;
+mod restore;
+mod root;
+mod selective;
 mod setup;
+mod shared;
+mod status;
 mod sync;
+mod trash;
 mod tray;
 mod user;
 extern crate clap;
@@ -23,22 +37,53 @@ use clap::{App, SubCommand};
 //  + Add functionality to get out of some errors (like with not existing authorization and etc.)
 //  - Synced folder can be either the whole drive or folder in the root of the drive
 
-fn main() -> Result<()> {
+#[tokio::main]
+async fn main() -> Result<()> {
     let cmd = App::new("Ocean Drive")
         .version(env!("CARGO_PKG_VERSION"))
         .author(env!("CARGO_PKG_AUTHORS"))
         .about(env!("CARGO_PKG_DESCRIPTION"))
         .subcommand(setup::root_subcommand())
-        .subcommand(SubCommand::with_name("run").about("[DEFAULT] Start synchronization."))
-        .get_matches();
+        .subcommand(shared::root_subcommand())
+        .subcommand(restore::root_subcommand())
+        .subcommand(trash::root_subcommand())
+        .subcommand(deletions::root_subcommand())
+        .subcommand(root::root_subcommand())
+        .subcommand(conflicts::root_subcommand())
+        .subcommand(check_ignore::root_subcommand())
+        .subcommand(selective::root_subcommand())
+        .subcommand(hydrate::root_subcommand())
+        .subcommand(hydrate::dehydrate_subcommand())
+        .subcommand(oneshot::root_subcommand())
+        .subcommand(queue::root_subcommand())
+        .subcommand(status::root_subcommand())
+        .subcommand(SubCommand::with_name("run").about("[DEFAULT] Start synchronization."));
+    #[cfg(feature = "mount")]
+    let cmd = cmd.subcommand(mount::root_subcommand());
+    let cmd = cmd.get_matches();
 
     // let c = files::read_toml::<config::Config>("./config.toml");
     // TODO: Add check for config file in the ~/.config folder. Create if does not exist. Or use the provided one from cli args
     let subcmd = cmd.subcommand_name().unwrap_or("run");
 
     match subcmd {
-        "setup" => setup::run(cmd.subcommand().1.unwrap()),
-        "run" => sync::run(),
+        "setup" => setup::run(cmd.subcommand().1.unwrap()).await,
+        "shared" => shared::run(cmd.subcommand().1.unwrap()).await,
+        "restore" => restore::run(cmd.subcommand().1.unwrap()).await,
+        "trash" => trash::run(cmd.subcommand().1.unwrap()),
+        "deletions" => deletions::run(cmd.subcommand().1.unwrap()).await,
+        "root" => root::run(cmd.subcommand().1.unwrap()).await,
+        "conflicts" => conflicts::run(cmd.subcommand().1.unwrap()).await,
+        "check-ignore" => check_ignore::run(cmd.subcommand().1.unwrap()),
+        "selective" => selective::run().await,
+        "hydrate" => hydrate::run(cmd.subcommand().1.unwrap(), true).await,
+        "dehydrate" => hydrate::run(cmd.subcommand().1.unwrap(), false).await,
+        "sync" => oneshot::run(cmd.subcommand().1.unwrap()).await,
+        "queue" => queue::run(),
+        "status" => status::run(),
+        #[cfg(feature = "mount")]
+        "mount" => mount::run(cmd.subcommand().1.unwrap()).await,
+        "run" => sync::run().await,
         _ => {
             bail!("Unknown subcommand. Try 'ocean-drive --help'");
         }
@@ -0,0 +1,83 @@
+/*
+    On-disk cache of file content for the mount. Content is split into blocks of the same size,
+    every block is downloaded with a range request only once. Blocks are kept per md5 of the file,
+    so a changed file never returns old content.
+*/
+use crate::google_drive::Client;
+use anyhow::Result;
+use std::{fs, path::PathBuf};
+
+pub const BLOCK_SIZE: u64 = 1024 * 1024;
+
+pub struct BlockCache {
+    dir: PathBuf,
+}
+
+impl BlockCache {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// Reads `len` bytes starting from `offset`. `size` is the size of the whole file
+    pub async fn read(
+        &self,
+        client: &Client,
+        id: &str,
+        md5: &str,
+        size: u64,
+        offset: u64,
+        len: u64,
+    ) -> Result<Vec<u8>> {
+        let end = (offset + len).min(size);
+        if offset >= end {
+            return Ok(vec![]);
+        }
+
+        let mut data = vec![];
+        for block in offset / BLOCK_SIZE..=(end - 1) / BLOCK_SIZE {
+            let content = self.block(client, id, md5, size, block).await?;
+            let start = block * BLOCK_SIZE;
+
+            let from = offset.saturating_sub(start) as usize;
+            let to = ((end - start) as usize).min(content.len());
+            if from < to {
+                data.extend_from_slice(&content[from..to]);
+            }
+        }
+
+        Ok(data)
+    }
+
+    async fn block(
+        &self,
+        client: &Client,
+        id: &str,
+        md5: &str,
+        size: u64,
+        block: u64,
+    ) -> Result<Vec<u8>> {
+        let dir = self.dir.join(id).join(md5);
+        let path = dir.join(block.to_string());
+
+        if let Ok(content) = fs::read(&path) {
+            return Ok(content);
+        }
+
+        let start = block * BLOCK_SIZE;
+        let content = client
+            .download_range(id, start, BLOCK_SIZE.min(size - start))
+            .await?;
+
+        // Blocks of older content aren't needed anymore
+        if !dir.exists() {
+            let _ = fs::remove_dir_all(self.dir.join(id));
+        }
+
+        // Failing cache is not a reason to fail the read
+        if fs::create_dir_all(&dir).is_ok() {
+            let _ = fs::write(&path, &content);
+        }
+
+        Ok(content)
+    }
+}
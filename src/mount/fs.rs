@@ -0,0 +1,296 @@
+/*
+    Read-only FUSE filesystem over the drive folder. Every item gets an inode when it's listed for
+    the first time. Listings are kept for a short time, so browsing doesn't hit the API on every
+    `ls`, but changes in the drive still show up.
+*/
+use crate::{
+    auth,
+    google_drive::{errors::DriveError, types::File, Client},
+    mount::cache::BlockCache,
+};
+use anyhow::Result;
+use chrono::DateTime;
+use fuser::{
+    FileAttr, FileType, Filesystem, ReplyAttr, ReplyData, ReplyDirectory, ReplyEntry, Request,
+};
+use std::{
+    collections::HashMap,
+    ffi::OsStr,
+    future::Future,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tokio::runtime::Handle;
+
+/// How long the kernel and the filesystem trust attributes and listings
+const TTL: Duration = Duration::from_secs(30);
+const ROOT_INO: u64 = fuser::FUSE_ROOT_ID;
+const FOLDER_MIME: &str = "application/vnd.google-apps.folder";
+
+struct Node {
+    id: String,
+    /// Inode of the directory the item was listed in. The root is its own parent
+    parent: u64,
+    name: String,
+    is_dir: bool,
+    size: u64,
+    md5: Option<String>,
+    mtime: SystemTime,
+}
+
+pub struct DriveFs {
+    client: Arc<Client>,
+    /// Requests run on the runtime, the filesystem thread waits for them
+    runtime: Handle,
+    cache: BlockCache,
+    nodes: HashMap<u64, Node>,
+    inodes: HashMap<String, u64>,
+    /// Inodes of directory children with the time they were listed
+    listings: HashMap<u64, (Instant, Vec<u64>)>,
+    uid: u32,
+    gid: u32,
+}
+
+impl DriveFs {
+    pub fn new(client: Arc<Client>, runtime: Handle, root: &File, cache: BlockCache) -> Self {
+        let mut fs = Self {
+            client,
+            runtime,
+            cache,
+            nodes: HashMap::new(),
+            inodes: HashMap::new(),
+            listings: HashMap::new(),
+            uid: unsafe { libc::getuid() },
+            gid: unsafe { libc::getgid() },
+        };
+
+        fs.nodes.insert(ROOT_INO, Node::from_file(root, ROOT_INO));
+        fs.inodes.insert(root.id.clone().unwrap(), ROOT_INO);
+        fs
+    }
+
+    /// Runs the request and retries it once if the authorization is out of date
+    fn request<T, F, Fut>(&self, f: F) -> Result<T>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        self.runtime.block_on(async {
+            match f().await {
+                Err(e)
+                    if matches!(
+                        e.downcast_ref::<DriveError>(),
+                        Some(DriveError::Unauthorized)
+                    ) =>
+                {
+                    auth::util::update_for_shared_client(&self.client).await?;
+                    f().await
+                }
+                res => res,
+            }
+        })
+    }
+
+    /// Returns inodes of the directory children, listing them again if needed
+    fn children(&mut self, ino: u64) -> Result<Vec<u64>> {
+        if let Some((listed, children)) = self.listings.get(&ino) {
+            if listed.elapsed() < TTL {
+                return Ok(children.clone());
+            }
+        }
+
+        let id = self.nodes[&ino].id.clone();
+        let query = format!("'{}' in parents and trashed = false", id);
+        let client = &self.client;
+        let files = self
+            .request(|| client.list_files(Some(&query), None))?
+            .files;
+
+        let mut children = vec![];
+        for f in files {
+            // Google Docs have no content to read, so they are not shown
+            if !f
+                .mime_type
+                .as_deref()
+                .unwrap_or("")
+                .starts_with("application/vnd.google-apps")
+                || f.mime_type.as_deref() == Some(FOLDER_MIME)
+            {
+                children.push(self.remember(&f, ino));
+            }
+        }
+
+        self.listings
+            .insert(ino, (Instant::now(), children.clone()));
+        Ok(children)
+    }
+
+    fn remember(&mut self, f: &File, parent: u64) -> u64 {
+        let id = f.id.clone().unwrap();
+        let next = self.nodes.len() as u64 + 1;
+        let ino = *self.inodes.entry(id).or_insert(next);
+
+        self.nodes.insert(ino, Node::from_file(f, parent));
+        ino
+    }
+
+    fn attr(&self, ino: u64) -> Option<FileAttr> {
+        let node = self.nodes.get(&ino)?;
+
+        Some(FileAttr {
+            ino,
+            size: node.size,
+            blocks: node.size.div_ceil(512),
+            atime: node.mtime,
+            mtime: node.mtime,
+            ctime: node.mtime,
+            crtime: node.mtime,
+            kind: if node.is_dir {
+                FileType::Directory
+            } else {
+                FileType::RegularFile
+            },
+            perm: if node.is_dir { 0o555 } else { 0o444 },
+            nlink: if node.is_dir { 2 } else { 1 },
+            uid: self.uid,
+            gid: self.gid,
+            rdev: 0,
+            blksize: 4096,
+            flags: 0,
+        })
+    }
+}
+
+impl Node {
+    fn from_file(f: &File, parent: u64) -> Self {
+        let mtime = f
+            .modified_time
+            .as_ref()
+            .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+            .map(|t| UNIX_EPOCH + Duration::from_secs(t.timestamp().max(0) as u64))
+            .unwrap_or(UNIX_EPOCH);
+
+        Self {
+            id: f.id.clone().unwrap(),
+            parent,
+            name: f.name.clone().unwrap_or_default(),
+            is_dir: f.mime_type.as_deref() == Some(FOLDER_MIME),
+            size: f.size.as_ref().and_then(|s| s.parse().ok()).unwrap_or(0),
+            md5: f.md5.clone(),
+            mtime,
+        }
+    }
+}
+
+impl Filesystem for DriveFs {
+    fn lookup(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        let children = match self.children(parent) {
+            Ok(c) => c,
+            Err(e) => {
+                eprintln!("Mount: {}", e);
+                return reply.error(libc::EIO);
+            }
+        };
+
+        let found = children
+            .into_iter()
+            .find(|ino| OsStr::new(&self.nodes[ino].name) == name);
+
+        match found.and_then(|ino| self.attr(ino)) {
+            Some(attr) => reply.entry(&TTL, &attr, 0),
+            None => reply.error(libc::ENOENT),
+        }
+    }
+
+    fn getattr(&mut self, _req: &Request, ino: u64, reply: ReplyAttr) {
+        match self.attr(ino) {
+            Some(attr) => reply.attr(&TTL, &attr),
+            None => reply.error(libc::ENOENT),
+        }
+    }
+
+    fn readdir(
+        &mut self,
+        _req: &Request,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        mut reply: ReplyDirectory,
+    ) {
+        let children = match self.children(ino) {
+            Ok(c) => c,
+            Err(e) => {
+                eprintln!("Mount: {}", e);
+                return reply.error(libc::EIO);
+            }
+        };
+
+        let mut entries = vec![
+            (ino, FileType::Directory, String::from(".")),
+            (
+                self.nodes[&ino].parent,
+                FileType::Directory,
+                String::from(".."),
+            ),
+        ];
+        for child in children {
+            let node = &self.nodes[&child];
+            let kind = if node.is_dir {
+                FileType::Directory
+            } else {
+                FileType::RegularFile
+            };
+            entries.push((child, kind, node.name.clone()));
+        }
+
+        for (i, (ino, kind, name)) in entries.into_iter().enumerate().skip(offset as usize) {
+            // Offset of the next entry is passed back by the kernel when the buffer is full
+            if reply.add(ino, (i + 1) as i64, kind, name) {
+                break;
+            }
+        }
+        reply.ok();
+    }
+
+    fn read(
+        &mut self,
+        _req: &Request,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        size: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyData,
+    ) {
+        let node = match self.nodes.get(&ino) {
+            Some(n) if !n.is_dir => n,
+            Some(_) => return reply.error(libc::EISDIR),
+            None => return reply.error(libc::ENOENT),
+        };
+
+        let (id, md5, file_size) = (
+            node.id.clone(),
+            node.md5.clone().unwrap_or_default(),
+            node.size,
+        );
+        let res = self.request(|| {
+            self.cache.read(
+                &self.client,
+                &id,
+                &md5,
+                file_size,
+                offset as u64,
+                size as u64,
+            )
+        });
+
+        match res {
+            Ok(data) => reply.data(&data),
+            Err(e) => {
+                eprintln!("Mount: {}", e);
+                reply.error(libc::EIO)
+            }
+        }
+    }
+}
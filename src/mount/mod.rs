@@ -0,0 +1,46 @@
+/*
+    Read-only FUSE mount of the synced drive folder, for browsing it without syncing. Built only
+    with the `mount` feature.
+*/
+mod cache;
+mod fs;
+
+use crate::{sync, user};
+use anyhow::{Context, Result};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use fuser::MountOption;
+use tokio::runtime::Handle;
+
+pub fn root_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("mount")
+        .about("Mounts the drive folder as a read-only filesystem.")
+        .usage("ocean-drive mount <mountpoint>")
+        .after_help("Unmount with `fusermount -u <mountpoint>`")
+        .arg(
+            Arg::with_name("mountpoint")
+                .required(true)
+                .help("Existing empty directory"),
+        )
+}
+
+pub async fn run(m: &ArgMatches<'_>) -> Result<()> {
+    let mountpoint = m.value_of("mountpoint").unwrap().to_string();
+    let ctx = sync::load_context().await?;
+    let cache = cache::BlockCache::new(user::get_home()?.join(".cache/ocean-drive/blocks"));
+    let drive_fs = fs::DriveFs::new(ctx.client, Handle::current(), &ctx.remote_dir, cache);
+
+    println!("Info: mounting drive at {:?}", mountpoint);
+    // Filesystem calls wait for requests on the runtime, so they can't come from one of its tasks
+    tokio::task::spawn_blocking(move || {
+        fuser::mount2(
+            drive_fs,
+            &mountpoint,
+            &[
+                MountOption::RO,
+                MountOption::FSName(String::from("ocean-drive")),
+            ],
+        )
+        .with_context(|| format!("Failed to mount at {:?}", mountpoint))
+    })
+    .await?
+}
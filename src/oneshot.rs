@@ -0,0 +1,87 @@
+/* One-shot synchronization for scripts and cron jobs */
+
+use crate::sync::{
+    self,
+    once::{self, Options},
+};
+use anyhow::{bail, Result};
+use clap::{App, Arg, ArgMatches, SubCommand};
+
+pub fn root_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("sync")
+        .about("Syncs every folder once and exits.")
+        .usage("ocean-drive sync --once [--direction <direction>] [--dry-run [--json]]")
+        .after_help("Exits with non-zero code if there were errors or conflicts left unresolved")
+        .arg(
+            Arg::with_name("once")
+                .long("once")
+                .help("Exit after one full sync. Without it the daemon is started like with `ocean-drive run`"),
+        )
+        .arg(
+            Arg::with_name("direction")
+                .long("direction")
+                .takes_value(true)
+                .possible_values(&["two-way", "download-only", "upload-only"])
+                .help("Overrides direction of every folder for this sync"),
+        )
+        .arg(
+            Arg::with_name("dry-run")
+                .long("dry-run")
+                .help("Only prints the plan of changes that would be made (implies --once)"),
+        )
+        .arg(
+            Arg::with_name("json")
+                .long("json")
+                .help("Prints the plan and the summary as JSON"),
+        )
+}
+
+pub async fn run(m: &ArgMatches<'_>) -> Result<()> {
+    let opts = Options {
+        direction: m.value_of("direction").map(|d| d.parse()).transpose()?,
+        dry_run: m.is_present("dry-run"),
+    };
+    if !m.is_present("once") && !opts.dry_run {
+        return sync::run().await;
+    }
+
+    let ctx = sync::load_context().await?;
+    let summary = once::run(&ctx, &opts).await?;
+
+    if m.is_present("json") {
+        println!("{}", serde_json::to_string_pretty(&summary)?);
+    } else {
+        for op in &summary.operations {
+            println!("{}", op);
+        }
+        for e in &summary.errors {
+            eprintln!("Error: {}", e);
+        }
+
+        println!(
+            "\n{}{} download(s), {} upload(s), {} rename(s), {} deletion(s), {} conflict(s)",
+            if opts.dry_run { "Dry run: " } else { "" },
+            summary.count("download"),
+            summary.count("upload"),
+            summary.count("rename"),
+            summary.count("delete"),
+            summary.count("conflict"),
+        );
+    }
+
+    if summary.conflicts > 0 {
+        eprintln!(
+            "Warn: {} conflict(s) are not resolved.\nTip: run `ocean-drive conflicts` to review conflicts",
+            summary.conflicts
+        );
+    }
+    if !summary.is_ok() {
+        bail!(
+            "Sync finished with {} error(s) and {} unresolved conflict(s)",
+            summary.errors.len(),
+            summary.conflicts
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,46 @@
+/* Review of local changes that wait to be sent to the drive */
+
+use crate::{
+    sync::{queue::Queue, state::StateStore},
+    user,
+};
+use anyhow::Result;
+use chrono::{Local, TimeZone};
+use clap::{App, SubCommand};
+use std::sync::Arc;
+
+pub fn root_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("queue")
+        .about("Lists local changes that are not in the drive yet.")
+        .usage("ocean-drive queue")
+        .after_help("Failed changes are retried by `ocean-drive run` with a growing delay")
+}
+
+pub fn run() -> Result<()> {
+    // Works without the network, so the state is read directly
+    let state = StateStore::new(user::get_home()?.join(".config/ocean-drive/state.json"));
+    let pending = Queue::new(Arc::new(state)).pending()?;
+
+    if pending.is_empty() {
+        println!("No changes pending");
+        return Ok(());
+    }
+
+    println!("{} change(s) pending:", pending.len());
+    for item in pending {
+        println!("  {}", item.op);
+        if let Some(e) = item.last_error {
+            let retry = Local
+                .timestamp(item.retry_at, 0)
+                .format("%Y-%m-%d %H:%M:%S");
+            println!(
+                "      {} attempt(s), next one at {}. Last error: {}",
+                item.attempts,
+                retry,
+                e.lines().next().unwrap_or("")
+            );
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,118 @@
+/* Restores items deleted from the synced folders out of the drive trash */
+
+use crate::{
+    google_drive::types::File,
+    readline,
+    sync::{self, remote::RemoteDaemon, versions::Versions},
+};
+use anyhow::{bail, Result};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use std::collections::HashSet;
+
+pub fn root_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("restore")
+        .about("Restores deleted files from the trash of your drive and downloads them again.")
+        .usage("ocean-drive restore [NAME]...")
+        .after_help("Without arguments lists trashed items and asks which of them to restore")
+        .arg(
+            Arg::with_name("items")
+                .multiple(true)
+                .help("Names or ids of trashed items to restore"),
+        )
+        .arg(
+            Arg::with_name("all")
+                .long("all")
+                .help("Restore every trashed item from the synced folders"),
+        )
+}
+
+pub async fn run(m: &ArgMatches<'_>) -> Result<()> {
+    let ctx = sync::load_context().await?;
+    let client = &*ctx.client;
+    let snapshot = Versions::snapshot(&ctx.versions)?;
+    let mut v_list = snapshot.clone();
+
+    // Only items right inside of the synced folders can be restored, everything deeper is
+    // restored together with its parent
+    let mut folders: HashSet<String> = ctx.pairs.iter().map(|p| p.id.clone()).collect();
+    v_list
+        .iter()
+        .filter(|(_, v)| v.is_folder && v.shortcut.is_none())
+        .for_each(|(id, _)| {
+            folders.insert(id.clone());
+        });
+
+    let trashed: Vec<File> = client
+        .list_files(
+            Some("trashed = true"),
+            Some("files(id, name, mimeType, parents)"),
+        )
+        .await?
+        .files
+        .into_iter()
+        .filter(|f| {
+            f.parents
+                .as_ref()
+                .map(|p| p.iter().any(|id| folders.contains(id)))
+                .unwrap_or(false)
+        })
+        .collect();
+
+    if trashed.is_empty() {
+        println!("There's nothing to restore");
+        return Ok(());
+    }
+
+    let selected: Vec<&File> = if m.is_present("all") {
+        trashed.iter().collect()
+    } else if let Some(items) = m.values_of("items") {
+        let mut selected = vec![];
+        for item in items {
+            match trashed
+                .iter()
+                .find(|f| f.id.as_deref() == Some(item) || f.name.as_deref() == Some(item))
+            {
+                Some(f) => selected.push(f),
+                None => bail!("No item {:?} found in the trash", item),
+            }
+        }
+        selected
+    } else {
+        for (i, f) in trashed.iter().enumerate() {
+            println!("{}. {}", i + 1, f.name.as_ref().unwrap());
+        }
+
+        let answer = readline::prompt("Which items to restore? (Numbers separated by spaces)")
+            .unwrap_or_default();
+        answer
+            .split_whitespace()
+            .filter_map(|n| n.parse::<usize>().ok())
+            .filter_map(|n| trashed.get(n.wrapping_sub(1)))
+            .collect()
+    };
+
+    if selected.is_empty() {
+        println!("Nothing selected");
+        return Ok(());
+    }
+
+    for f in selected {
+        let restored = client.untrash_file(f.id.clone().unwrap()).await?;
+        println!("Restored {:?}", f.name.as_ref().unwrap());
+
+        // Parent dirs may keep their versions, so make sure remote daemon looks into them again
+        for parent in restored.parents.unwrap_or_default() {
+            Versions::invalidate(&parent, &mut v_list);
+        }
+    }
+
+    Versions::commit(&ctx.versions, &snapshot, v_list)?;
+
+    println!("Downloading restored items");
+    for pair in &ctx.pairs {
+        let d = RemoteDaemon::new(&ctx, pair.clone())?;
+        d.sync().await?;
+    }
+
+    Ok(())
+}
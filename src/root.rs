@@ -0,0 +1,37 @@
+/* Checks of local dirs that protect the drive from syncing an unmounted or replaced dir */
+
+use crate::sync::{self, root};
+use anyhow::Result;
+use clap::{App, ArgMatches, SubCommand};
+
+pub fn root_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("root")
+        .about("Shows if local directories are safe to sync.")
+        .usage("ocean-drive root [SUBCOMMAND (if needed)]")
+        .after_help("Without subcommands shows the check result for every local directory")
+        .subcommand(
+            SubCommand::with_name("accept").about(
+                "Marks current local directories as the right ones (e.g. after moving them)",
+            ),
+        )
+}
+
+pub async fn run(m: &ArgMatches<'_>) -> Result<()> {
+    let ctx = sync::load_context().await?;
+
+    for pair in &ctx.pairs {
+        if m.subcommand_name() == Some("accept") {
+            root::accept(pair, &ctx.state)?;
+            println!("Accepted {:?}", pair.local_dir);
+            continue;
+        }
+
+        // Only shows the result, roots are marked by the daemons and `accept`
+        match root::check_dry(pair, &ctx.state) {
+            Ok(_) => println!("{:?}: ok", pair.local_dir),
+            Err(e) => println!("{:?}: sync is suspended, {}", pair.local_dir, e),
+        }
+    }
+
+    Ok(())
+}
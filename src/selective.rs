@@ -0,0 +1,126 @@
+/* Interactive choice of remote folders that are synced (selective sync) */
+
+use crate::{
+    google_drive::{types::File, Client},
+    readline, setup, sync,
+};
+use anyhow::Result;
+use clap::{App, SubCommand};
+
+pub fn root_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("selective")
+        .about("Chooses which folders of your drive are synced to this computer.")
+        .usage("ocean-drive selective")
+        .after_help("Changes take effect when `ocean-drive run` is started next time")
+}
+
+pub async fn run() -> Result<()> {
+    let ctx = sync::load_context().await?;
+    let mut config = ctx.config.clone();
+    let client = &*ctx.client;
+
+    // Every opened folder: (id, path relative to the synced folder)
+    let mut trail = vec![(ctx.remote_dir.id.clone().unwrap(), String::new())];
+    let mut changed = false;
+
+    loop {
+        let (id, path) = trail.last().unwrap().clone();
+        let folders = list_folders(client, &id).await?;
+
+        println!("\n/{}", path);
+        for (i, f) in folders.iter().enumerate() {
+            let rel = join(&path, f.name.as_ref().unwrap());
+            let mark = if is_excluded(&config.exclude, f, &rel) {
+                " "
+            } else {
+                "x"
+            };
+            println!("  {:>3}. [{}] {}", i + 1, mark, f.name.as_ref().unwrap());
+        }
+
+        let ans = readline::prompt(
+            "\nNumber to toggle sync, 'o <number>' to open, '..' to go up, 's' to save, 'q' to quit",
+        )
+        .unwrap_or_default();
+        let mut words = ans.split_whitespace();
+
+        match (words.next(), words.next()) {
+            (Some("q"), _) | (None, _) => {
+                if changed && !readline::binary_prompt("Quit without saving?") {
+                    continue;
+                }
+                return Ok(());
+            }
+            (Some("s"), _) => break,
+            (Some(".."), _) => {
+                if trail.len() > 1 {
+                    trail.pop();
+                }
+            }
+            (Some("o"), Some(n)) => match pick(&folders, n) {
+                Some(f) => {
+                    let rel = join(&path, f.name.as_ref().unwrap());
+                    trail.push((f.id.clone().unwrap(), rel));
+                }
+                None => println!("No folder {:?}", n),
+            },
+            (Some(n), None) => match pick(&folders, n) {
+                Some(f) => {
+                    let rel = join(&path, f.name.as_ref().unwrap());
+                    let id = f.id.as_ref().unwrap();
+
+                    if is_excluded(&config.exclude, f, &rel) {
+                        config
+                            .exclude
+                            .retain(|e| e != id && e.trim_matches('/') != rel);
+                    } else {
+                        config.exclude.push(rel);
+                    }
+                    changed = true;
+                }
+                None => println!("No folder {:?}", n),
+            },
+            _ => println!("Unknown command {:?}", ans),
+        }
+    }
+
+    setup::save_config(config)?;
+    println!("Saved. Restart `ocean-drive run` to apply changes");
+    Ok(())
+}
+
+async fn list_folders(client: &Client, id: &str) -> Result<Vec<File>> {
+    let mut folders = client
+        .list_files(
+            Some(&format!(
+                "'{}' in parents and mimeType = 'application/vnd.google-apps.folder' and trashed = false",
+                id
+            )),
+            None,
+        )
+        .await?
+        .files;
+    folders.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(folders)
+}
+
+fn is_excluded(exclude: &[String], f: &File, rel: &str) -> bool {
+    exclude
+        .iter()
+        .any(|e| Some(e) == f.id.as_ref() || e.trim_matches('/') == rel)
+}
+
+fn pick<'a>(folders: &'a [File], n: &str) -> Option<&'a File> {
+    n.parse::<usize>()
+        .ok()
+        .and_then(|n| n.checked_sub(1))
+        .and_then(|i| folders.get(i))
+}
+
+fn join(path: &str, name: &str) -> String {
+    if path.is_empty() {
+        return name.to_string();
+    }
+    format!("{}/{}", path, name)
+}
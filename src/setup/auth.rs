@@ -9,10 +9,10 @@ use crate::{
 use anyhow::{bail, Result};
 use webbrowser;
 
-pub fn run() -> Result<()> {
+pub async fn run() -> Result<()> {
     let creds = get_client_creds();
     let redirect_uri = "http://localhost:8080";
-    let mut drive_client = Client::new(creds.0.clone(), creds.1.clone(), redirect_uri.to_string());
+    let drive_client = Client::new(creds.0.clone(), creds.1.clone(), redirect_uri.to_string());
 
     let user_consent_url = drive_client
         .get_user_authorization_url("https://www.googleapis.com/auth/drive", redirect_uri);
@@ -20,7 +20,7 @@ pub fn run() -> Result<()> {
     let auth_code = get_auth_code(user_consent_url);
 
     if let Ok(code) = auth_code {
-        let session = drive_client.authorize_with_code(code.to_string())?;
+        let session = drive_client.authorize_with_code(code.to_string()).await?;
 
         println!("App is authorized. Saving user credentials and session files.");
 
@@ -1,6 +1,16 @@
 /* Setup program to be ready to start */
 
-use crate::{files, google_drive::Config as DriveConfig, readline, user};
+use crate::{
+    files,
+    google_drive::Config as DriveConfig,
+    readline,
+    sync::{
+        bandwidth::BandwidthConfig, conflicts::ConflictsConfig, deletions::SafeguardConfig, ignore,
+        pairs::Direction, placeholders::PlaceholdersConfig, recycle_bin::RecycleBinConfig,
+        shortcuts::ShortcutPolicy, transfers::TransfersConfig,
+    },
+    user,
+};
 use anyhow::Result;
 use clap::ArgMatches;
 use serde::{Deserialize, Serialize};
@@ -19,25 +29,92 @@ pub use self::cli::*;
 #[derive(Deserialize, Serialize, Clone)]
 pub struct Config {
     pub local_dir: String,
+    /// What to do with drive shortcuts: "symlink", "copy" or "skip"
+    #[serde(default)]
+    pub shortcuts: ShortcutPolicy,
+    /// Local deletions are moved to the trash of the drive unless this is set
+    #[serde(default)]
+    pub permanent_delete: bool,
+    /// Gitignore-like patterns of items that are never synced. `.oceanignore` files add more
+    #[serde(default = "ignore::default_patterns")]
+    pub ignore: Vec<String>,
+    /// Remote folders that are not synced: paths relative to the synced folder (e.g.
+    /// "Photos/2019") or ids
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    /// "two-way", "download-only" or "upload-only"
+    #[serde(default)]
+    pub direction: Direction,
+    /// Owner (uid and gid) of files is saved in the drive along with permissions and restored
+    /// on download. Only root can give files to other users
+    #[serde(default)]
+    pub keep_owner: bool,
+    // Keep tables below plain values, otherwise config can't be serialized into TOML
     pub drive: DriveConfig,
+    /// Folders from "Shared with me" that are synced along with the main directory
+    #[serde(default)]
+    pub shared: Vec<SharedConfig>,
+    /// Retention of the local trash with files removed or overwritten by remote changes
+    #[serde(default)]
+    pub recycle_bin: RecycleBinConfig,
+    /// Limits of deletions that are applied without the confirmation
+    #[serde(default)]
+    pub safeguard: SafeguardConfig,
+    /// How to handle files changed both locally and in the drive
+    #[serde(default)]
+    pub conflicts: ConflictsConfig,
+    /// Files that are only downloaded on demand
+    #[serde(default)]
+    pub placeholders: PlaceholdersConfig,
+    /// How many files are transferred at once
+    #[serde(default)]
+    pub transfers: TransfersConfig,
+    /// Rate limits of uploads and downloads
+    #[serde(default)]
+    pub bandwidth: BandwidthConfig,
 }
 
-pub fn auth() -> Result<()> {
-    auth::run()
+#[derive(Deserialize, Serialize, Clone)]
+pub struct SharedConfig {
+    pub id: String,
+    pub name: String,
+    /// Where to keep the folder locally. When omitted, the folder goes to
+    /// `<local_dir>/Shared with me/<name>`
+    pub local_dir: Option<String>,
+    /// Overrides `direction` of the main directory for this folder
+    #[serde(default)]
+    pub direction: Option<Direction>,
+}
+
+/* Reads the configuration file from ~/.config/ocean-drive */
+pub fn read_config() -> Result<Config> {
+    let conf_file = user::get_home()?.join(".config/ocean-drive/config.toml");
+
+    files::read_toml::<Config>(conf_file)
+}
+
+pub fn save_config(config: Config) -> Result<()> {
+    let conf_file = user::get_home()?.join(".config/ocean-drive/config.toml");
+
+    files::write_toml::<Config>(config, conf_file)
+}
+
+pub async fn auth() -> Result<()> {
+    auth::run().await
 }
 
-pub fn run(m: &ArgMatches) -> Result<()> {
+pub async fn run(m: &ArgMatches<'_>) -> Result<()> {
     // Run only authorization subcommand if provided
     if let Some(sub) = m.subcommand_name() {
         if sub == "auth" {
-            return auth();
+            return auth().await;
         }
     }
 
     println!("Ocean Drive Setup");
     println!("Starting Authorization process\n");
 
-    auth()?;
+    auth().await?;
 
     println!("\nAuthoziation complete. Making sure configuration directory exists\n");
     create_configuration_dir()?;
@@ -79,10 +156,23 @@ fn gather_configurations() -> Result<()> {
 
     let config = Config {
         local_dir,
+        shortcuts: ShortcutPolicy::default(),
+        permanent_delete: false,
+        ignore: ignore::default_patterns(),
+        exclude: vec![],
+        direction: Direction::default(),
+        keep_owner: false,
         drive: DriveConfig { dir: remote_dir },
+        shared: vec![],
+        recycle_bin: RecycleBinConfig::default(),
+        safeguard: SafeguardConfig::default(),
+        conflicts: ConflictsConfig::default(),
+        placeholders: PlaceholdersConfig::default(),
+        transfers: TransfersConfig::default(),
+        bandwidth: BandwidthConfig::default(),
     };
 
-    files::write_toml::<Config>(config, home.join(".config/ocean-drive/config.toml"))?;
+    save_config(config)?;
 
     Ok(())
 }
@@ -0,0 +1,125 @@
+/* Management of folders from "Shared with me" that are synced along with the main directory */
+
+use crate::{
+    google_drive::types::File,
+    setup::{self, SharedConfig},
+    sync::{self, pairs},
+    user,
+};
+use anyhow::{bail, Result};
+use clap::{App, Arg, ArgMatches, SubCommand};
+
+pub fn root_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("shared")
+        .about("Manages folders from \"Shared with me\" that are synced along with your drive.")
+        .usage("ocean-drive shared [SUBCOMMAND (if needed)]")
+        .after_help("Without subcommands lists all folders shared with you")
+        .subcommand(
+            SubCommand::with_name("add")
+                .about("Starts syncing of the shared folder")
+                .arg(
+                    Arg::with_name("folder")
+                        .required(true)
+                        .help("Name or id of the shared folder"),
+                )
+                .arg(
+                    Arg::with_name("local-dir")
+                        .long("local-dir")
+                        .takes_value(true)
+                        .help("Local directory for the folder (Default: '<local_dir>/Shared with me/<name>')"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("remove")
+                .about("Stops syncing of the shared folder. Local files are kept")
+                .arg(
+                    Arg::with_name("folder")
+                        .required(true)
+                        .help("Name or id of the shared folder"),
+                ),
+        )
+}
+
+pub async fn run(m: &ArgMatches<'_>) -> Result<()> {
+    let mut config = setup::read_config()?;
+
+    match m.subcommand() {
+        ("add", Some(sub)) => {
+            let client = sync::setup_client(&user::get_home()?.join(".config/ocean-drive")).await?;
+            let folders = client.list_shared_folders().await?.files;
+            let name = sub.value_of("folder").unwrap();
+            let folder = find_folder(&folders, name)?;
+            let id = folder.id.clone().unwrap();
+
+            if config.shared.iter().any(|s| s.id == id) {
+                bail!("Folder {:?} is already synced", name);
+            }
+
+            let shared = SharedConfig {
+                id,
+                name: folder.name.clone().unwrap(),
+                local_dir: sub.value_of("local-dir").map(String::from),
+                direction: None,
+            };
+
+            let local_dir = pairs::shared_local_dir(&config, &shared);
+            if !pairs::can_edit(folder) {
+                println!("Info: Folder is read-only for you. It will be only downloaded.");
+            }
+            println!("Folder {:?} will be synced to {:?}", shared.name, local_dir);
+
+            config.shared.push(shared);
+            setup::save_config(config)
+        }
+        ("remove", Some(sub)) => {
+            let name = sub.value_of("folder").unwrap();
+            let count = config.shared.len();
+            config.shared.retain(|s| s.id != name && s.name != name);
+
+            if count == config.shared.len() {
+                bail!("Folder {:?} is not synced", name);
+            }
+
+            println!(
+                "Folder {:?} won't be synced anymore. Local files are kept.",
+                name
+            );
+            setup::save_config(config)
+        }
+        _ => {
+            let client = sync::setup_client(&user::get_home()?.join(".config/ocean-drive")).await?;
+            let folders = client.list_shared_folders().await?.files;
+
+            if folders.is_empty() {
+                println!("No folders are shared with you");
+                return Ok(());
+            }
+
+            for f in &folders {
+                let synced = config.shared.iter().any(|s| Some(&s.id) == f.id.as_ref());
+                println!(
+                    "[{}] {} ({}){}",
+                    if synced { "x" } else { " " },
+                    f.name.as_ref().unwrap(),
+                    f.id.as_ref().unwrap(),
+                    if pairs::can_edit(f) { "" } else { " read-only" }
+                );
+            }
+
+            Ok(())
+        }
+    }
+}
+
+fn find_folder<'a>(folders: &'a [File], name: &str) -> Result<&'a File> {
+    match folders
+        .iter()
+        .find(|f| f.id.as_deref() == Some(name) || f.name.as_deref() == Some(name))
+    {
+        Some(f) => Ok(f),
+        None => bail!(
+            "No folder {:?} found in \"Shared with me\".\nTip: run `ocean-drive shared` to see all shared folders",
+            name
+        ),
+    }
+}
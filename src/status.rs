@@ -0,0 +1,61 @@
+/* Short overview of the sync state, works without the network */
+
+use crate::{
+    sync::{network::PROBE_INTERVAL, state::StateStore},
+    user,
+};
+use anyhow::Result;
+use chrono::Utc;
+use clap::{App, SubCommand};
+
+pub fn root_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("status")
+        .about("Shows whether the drive is reachable and what waits to be synced.")
+        .usage("ocean-drive status")
+}
+
+pub fn run() -> Result<()> {
+    let state = StateStore::new(user::get_home()?.join(".config/ocean-drive/state.json")).load()?;
+
+    match state.network {
+        // The monitor probes regularly, so an old result means the app isn't running
+        Some(n) if Utc::now().timestamp() - n.checked_at <= 2 * PROBE_INTERVAL.as_secs() as i64 => {
+            if n.online {
+                println!("Network: online");
+            } else {
+                println!("Network: offline, sync is paused until it's back");
+            }
+        }
+        _ => println!("Network: unknown, `ocean-drive run` is not running"),
+    }
+
+    match state
+        .transfers
+        .filter(|t| t.is_current() && !t.files.is_empty())
+    {
+        Some(t) => {
+            println!("Transfers: {}", t.summary());
+            for f in &t.files {
+                println!("  {}", f);
+            }
+        }
+        None => println!("Transfers: none"),
+    }
+
+    println!("Pending changes: {}", state.queue.len());
+    println!("Pending deletions: {}", state.pending_deletions.len());
+    println!("Unresolved conflicts: {}", state.conflicts.len());
+    println!("Excluded folders: {}", state.excluded.len());
+
+    if !state.queue.is_empty() {
+        println!("Tip: run `ocean-drive queue` to see pending changes");
+    }
+    if !state.pending_deletions.is_empty() {
+        println!("Tip: run `ocean-drive deletions` to review pending deletions");
+    }
+    if !state.conflicts.is_empty() {
+        println!("Tip: run `ocean-drive conflicts` to review conflicts");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,142 @@
+/*
+    Bandwidth limits from the config. Limits for the current time of the day are applied to the
+    drive client every minute, and they can be turned off from the tray while the app runs.
+*/
+use crate::{google_drive::Client, sync::shutdown::Shutdown};
+use anyhow::{Context, Result};
+use chrono::{Local, NaiveTime};
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+/// How often schedules are checked
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Deserialize, Serialize, Clone, Default)]
+pub struct BandwidthConfig {
+    /// Max upload rate in KiB/s. `0` means no limit
+    #[serde(default)]
+    pub upload: u64,
+    /// Max download rate in KiB/s. `0` means no limit
+    #[serde(default)]
+    pub download: u64,
+    /// Other limits for some hours of the day (e.g. no limits at night). The first matching one
+    /// is used
+    #[serde(default)]
+    pub schedule: Vec<Schedule>,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct Schedule {
+    /// Local time as "HH:MM". The period may go over midnight, e.g. from "22:00" to "07:00"
+    pub from: String,
+    pub to: String,
+    #[serde(default)]
+    pub upload: u64,
+    #[serde(default)]
+    pub download: u64,
+}
+
+pub struct Bandwidth {
+    config: BandwidthConfig,
+    /// Parsed `from` and `to` of every schedule
+    periods: Vec<(NaiveTime, NaiveTime)>,
+    client: Arc<Client>,
+    enabled: AtomicBool,
+}
+
+impl Bandwidth {
+    pub fn new(config: BandwidthConfig, client: Arc<Client>) -> Result<Self> {
+        let periods = config
+            .schedule
+            .iter()
+            .map(|s| Ok((parse_time(&s.from)?, parse_time(&s.to)?)))
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            config,
+            periods,
+            client,
+            enabled: AtomicBool::new(true),
+        })
+    }
+
+    /// Whether any limit is set, otherwise there's nothing to turn on or off
+    pub fn is_configured(&self) -> bool {
+        self.config.upload > 0
+            || self.config.download > 0
+            || self
+                .config
+                .schedule
+                .iter()
+                .any(|s| s.upload > 0 || s.download > 0)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::SeqCst)
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::SeqCst);
+        self.apply();
+    }
+
+    /// Sets limits for the current time to the client
+    pub fn apply(&self) {
+        let (upload, download) = if self.is_enabled() {
+            self.limits_at(Local::now().time())
+        } else {
+            (0, 0)
+        };
+
+        self.client
+            .set_limits(Some(upload * 1024), Some(download * 1024));
+    }
+
+    /// Applies limits until the app is stopped, so schedules take effect
+    pub async fn monitor(&self, shutdown: &Shutdown) {
+        loop {
+            self.apply();
+            if shutdown.wait(CHECK_INTERVAL).await {
+                return;
+            }
+        }
+    }
+
+    /// Upload and download limits in KiB/s
+    fn limits_at(&self, time: NaiveTime) -> (u64, u64) {
+        let current = self
+            .config
+            .schedule
+            .iter()
+            .zip(&self.periods)
+            .find(|(_, (from, to))| is_between(time, *from, *to));
+
+        match current {
+            Some((s, _)) => (s.upload, s.download),
+            None => (self.config.upload, self.config.download),
+        }
+    }
+}
+
+fn parse_time(s: &str) -> Result<NaiveTime> {
+    NaiveTime::parse_from_str(s, "%H:%M").with_context(|| {
+        format!(
+            "Invalid time {:?} in the bandwidth schedule, it should be like \"22:30\"",
+            s
+        )
+    })
+}
+
+fn is_between(time: NaiveTime, from: NaiveTime, to: NaiveTime) -> bool {
+    if from <= to {
+        from <= time && time < to
+    } else {
+        time >= from || time < to
+    }
+}
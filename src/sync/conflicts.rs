@@ -0,0 +1,352 @@
+/*
+    Detection and resolution of conflicts, when a file was changed both locally and in the drive
+    since the last sync. Changes are found by comparing md5 of the last synced version (base) with
+    md5 of the local file and of the remote one.
+    How conflicts are resolved is configured with a strategy in the config.
+*/
+use crate::{
+    files,
+    google_drive::Client,
+    sync::{
+        merge::{self, MergeBases},
+        state::StateStore,
+        util,
+        versions::{Version, Versions, VersionsList},
+    },
+};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum Strategy {
+    /// Remote content takes the place, local one is saved as a copy next to it
+    #[default]
+    KeepBoth,
+    /// Content that was modified later wins
+    NewestWins,
+    LocalWins,
+    RemoteWins,
+    /// Files are not synced until the user resolves conflicts with `ocean-drive conflicts`
+    Ask,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct ConflictsConfig {
+    /// "keep-both", "newest-wins", "local-wins", "remote-wins" or "ask"
+    #[serde(default)]
+    pub strategy: Strategy,
+    /// Name of the copy with local content. `{name}`, `{ext}` (with the dot) and `{date}` are
+    /// replaced with values of the conflicting file
+    #[serde(default = "default_copy_name")]
+    pub copy_name: String,
+    /// Globs of text file names (e.g. "*.md") that are merged line by line when both sides
+    /// changed different lines
+    #[serde(default)]
+    pub merge: Vec<String>,
+}
+
+fn default_copy_name() -> String {
+    String::from("{name} (conflict {date}){ext}")
+}
+
+impl Default for ConflictsConfig {
+    fn default() -> Self {
+        Self {
+            strategy: Strategy::default(),
+            copy_name: default_copy_name(),
+            merge: vec![],
+        }
+    }
+}
+
+/// Which side was changed since the last sync
+#[derive(PartialEq, Debug)]
+pub enum Change {
+    None,
+    Local,
+    Remote,
+    Both,
+}
+
+/// Compares md5 of the base with local and remote ones. `None` means that the side has no content
+pub fn detect(base: Option<&String>, local: Option<&String>, remote: Option<&String>) -> Change {
+    if local == remote {
+        Change::None
+    } else if local == base {
+        Change::Remote
+    } else if remote == base {
+        Change::Local
+    } else {
+        Change::Both
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Resolution {
+    KeepLocal,
+    KeepRemote,
+    KeepBoth,
+    /// Changes of both sides were merged into one file
+    Merge,
+    /// Nothing is synced until the user decides
+    Postpone,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Conflict {
+    /// Id of the file in the drive
+    pub id: String,
+    pub path: String,
+    pub parent_id: String,
+    pub base_md5: Option<String>,
+    pub local_md5: String,
+    pub remote_md5: Option<String>,
+    /// RFC 3339 time of the remote change
+    pub remote_modified: Option<String>,
+    /// Local content can't be uploaded, so it can't win or be merged
+    #[serde(default)]
+    pub read_only: bool,
+}
+
+pub struct Resolver {
+    config: ConflictsConfig,
+    state: Arc<StateStore>,
+    bases: MergeBases,
+    /// Uploads keep the owner of the local file in the drive, like the local daemon does
+    keep_owner: bool,
+}
+
+impl Resolver {
+    pub fn new(
+        config: ConflictsConfig,
+        state: Arc<StateStore>,
+        bases_dir: PathBuf,
+        keep_owner: bool,
+    ) -> Result<Self> {
+        let bases = MergeBases::new(bases_dir, &config.merge)?;
+
+        Ok(Self {
+            config,
+            state,
+            bases,
+            keep_owner,
+        })
+    }
+
+    pub fn bases(&self) -> &MergeBases {
+        &self.bases
+    }
+
+    /// Merges text files if possible, otherwise decides with the strategy and applies the result
+    pub async fn resolve(
+        &self,
+        c: &Conflict,
+        client: &Client,
+        v_list: &mut VersionsList,
+    ) -> Result<Resolution> {
+        if !c.read_only && self.merge(c, client, v_list).await? {
+            return Ok(Resolution::Merge);
+        }
+
+        let res = self.decide(c)?;
+        self.apply(res, c, client, v_list).await?;
+        Ok(res)
+    }
+
+    /// Picks the resolution according to the strategy. Conflicts of `ask` strategy are saved in the
+    /// state and postponed
+    pub fn decide(&self, c: &Conflict) -> Result<Resolution> {
+        let res = match self.config.strategy {
+            Strategy::KeepBoth => Resolution::KeepBoth,
+            Strategy::LocalWins | Strategy::NewestWins if c.read_only => Resolution::KeepBoth,
+            Strategy::LocalWins => Resolution::KeepLocal,
+            Strategy::RemoteWins => Resolution::KeepRemote,
+            Strategy::NewestWins => newest(c),
+            Strategy::Ask => {
+                let is_new = self.state.update(|s| {
+                    let is_new = !s.conflicts.iter().any(|p| p.id == c.id);
+                    s.conflicts.retain(|p| p.id != c.id);
+                    s.conflicts.push(c.clone());
+                    is_new
+                })?;
+
+                if is_new {
+                    eprintln!("Warn: {:?} was changed both locally and in the drive, it won't be synced until the conflict is resolved.\nTip: run `ocean-drive conflicts` to review conflicts", c.path);
+                }
+                return Ok(Resolution::Postpone);
+            }
+        };
+
+        println!("Info: Conflict in {:?} resolved with {:?}", c.path, res);
+        Ok(res)
+    }
+
+    /// Applies the resolution to the local file and the versions list.
+    /// Remote content is not downloaded here. Instead, the file gets an outdated version, so the
+    /// remote daemon replaces it on the next sync (local content goes to the local trash then)
+    pub async fn apply(
+        &self,
+        res: Resolution,
+        c: &Conflict,
+        client: &Client,
+        v_list: &mut VersionsList,
+    ) -> Result<()> {
+        match res {
+            Resolution::KeepLocal => {
+                let content = files::read_bytes(PathBuf::from(&c.path))?;
+                self.upload(c, content, client, v_list).await?;
+            }
+            Resolution::KeepRemote | Resolution::KeepBoth => {
+                if res == Resolution::KeepBoth {
+                    let copy = copy_path(&self.config.copy_name, Path::new(&c.path));
+                    fs::copy(&c.path, &copy)
+                        .with_context(|| format!("Failed to save local version of {:?}", c.path))?;
+                    println!(
+                        "Info: Local version of {:?} is saved as {:?}",
+                        c.path,
+                        copy.display()
+                    );
+                }
+
+                v_list.insert(
+                    c.id.clone(),
+                    Version {
+                        md5: Some(c.local_md5.clone()),
+                        path: c.path.clone(),
+                        version: String::new(),
+                        is_folder: false,
+                        parent_id: c.parent_id.clone(),
+                        read_only: false,
+                        shortcut: None,
+                        mode: None,
+                    },
+                );
+                Versions::invalidate(&c.parent_id, v_list);
+            }
+            Resolution::Merge | Resolution::Postpone => {}
+        }
+
+        Ok(())
+    }
+
+    /// Merges changes of both sides for text files that opted in. Returns false if the file
+    /// can't be merged
+    async fn merge(
+        &self,
+        c: &Conflict,
+        client: &Client,
+        v_list: &mut VersionsList,
+    ) -> Result<bool> {
+        let path = Path::new(&c.path);
+        if !self.bases.applies(path) {
+            return Ok(false);
+        }
+
+        let base = match c.base_md5.as_ref().and_then(|m| self.bases.load(&c.id, m)) {
+            Some(b) => b,
+            None => return Ok(false),
+        };
+        let local = files::read_bytes(path.to_path_buf())?;
+        let remote = client.download_file(&c.id).await?;
+
+        let merged = match (
+            String::from_utf8(base),
+            String::from_utf8(local),
+            String::from_utf8(remote),
+        ) {
+            (Ok(b), Ok(l), Ok(r)) => merge::merge(&b, &l, &r),
+            _ => None,
+        };
+        let merged = match merged {
+            Some(m) => m.into_bytes(),
+            None => return Ok(false),
+        };
+
+        fs::write(path, &merged)
+            .with_context(|| format!("Failed to write merged content to {:?}", c.path))?;
+        self.upload(c, merged, client, v_list).await?;
+
+        println!("Info: Local and remote changes of {:?} were merged", c.path);
+        Ok(true)
+    }
+
+    /// Uploads content of the local file along with its attributes and saves it as synced
+    async fn upload(
+        &self,
+        c: &Conflict,
+        content: Vec<u8>,
+        client: &Client,
+        v_list: &mut VersionsList,
+    ) -> Result<()> {
+        let path = Path::new(&c.path);
+        self.bases.save(&c.id, path, &content)?;
+        // Permissions go along with the content, so the version gets the mode of the local file
+        let attributes = util::local_attributes(path, self.keep_owner)?;
+        let updated = client
+            .update_file(c.id.clone(), content, attributes)
+            .await?;
+        let mode = updated.mode();
+
+        v_list.insert(
+            c.id.clone(),
+            Version {
+                md5: updated.md5,
+                path: c.path.clone(),
+                version: updated.version.unwrap_or(String::from("1")),
+                is_folder: false,
+                parent_id: c.parent_id.clone(),
+                read_only: false,
+                shortcut: None,
+                mode,
+            },
+        );
+
+        Ok(())
+    }
+}
+
+/// Tie or unknown modification time keeps both versions, so nothing is lost
+fn newest(c: &Conflict) -> Resolution {
+    let local = fs::metadata(&c.path)
+        .and_then(|m| m.modified())
+        .ok()
+        .map(DateTime::<Utc>::from);
+    let remote = c
+        .remote_modified
+        .as_ref()
+        .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+        .map(|t| t.with_timezone(&Utc));
+
+    match (local, remote) {
+        (Some(l), Some(r)) if l > r => Resolution::KeepLocal,
+        (Some(l), Some(r)) if l < r => Resolution::KeepRemote,
+        _ => Resolution::KeepBoth,
+    }
+}
+
+/// Builds a path for the copy of `p` next to it using the naming template
+pub fn copy_path(template: &str, p: &Path) -> PathBuf {
+    let name = p
+        .file_stem()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let ext = p
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+    let date = Local::now().format("%Y-%m-%d %H-%M-%S").to_string();
+
+    p.with_file_name(
+        template
+            .replace("{name}", &name)
+            .replace("{ext}", &ext)
+            .replace("{date}", &date),
+    )
+}
@@ -0,0 +1,192 @@
+/*
+    Safeguard against mass deletions. When too many files are deleted at once (local root was
+    unmounted, someone emptied the remote folder...), deletions are not applied but held in the
+    state until the user confirms or discards them.
+*/
+use crate::{
+    google_drive::Client,
+    setup::Config,
+    sync::{
+        recycle_bin::RecycleBin,
+        state::StateStore,
+        versions::{Version, Versions},
+    },
+};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Deletions that happen within this time are counted together
+const WINDOW: Duration = Duration::from_secs(60);
+/// Percentage limit is not checked for a handful of deletions, otherwise small trees would be
+/// always blocked
+const MIN_DELETIONS_FOR_PERCENT: usize = 10;
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct SafeguardConfig {
+    /// Max count of deletions at once. `0` disables the limit
+    #[serde(default = "default_max_deletions")]
+    pub max_deletions: usize,
+    /// Max percentage of tracked files that can be deleted at once. `0` disables the limit
+    #[serde(default = "default_max_deletions_percent")]
+    pub max_deletions_percent: usize,
+}
+
+fn default_max_deletions() -> usize {
+    100
+}
+
+fn default_max_deletions_percent() -> usize {
+    50
+}
+
+impl Default for SafeguardConfig {
+    fn default() -> Self {
+        Self {
+            max_deletions: default_max_deletions(),
+            max_deletions_percent: default_max_deletions_percent(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    /// File was deleted locally and waits to be deleted from the drive
+    Local,
+    /// File was trashed in the drive and waits to be removed locally
+    Remote,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Deletion {
+    /// Id of the file in the drive
+    pub id: String,
+    pub side: Side,
+    /// Local dir of the pair the file belongs to
+    pub root: String,
+    pub version: Version,
+}
+
+pub struct DeletionGuard {
+    config: SafeguardConfig,
+    state: Arc<StateStore>,
+    recent: Mutex<Vec<Instant>>,
+}
+
+impl DeletionGuard {
+    pub fn new(config: SafeguardConfig, state: Arc<StateStore>) -> Self {
+        Self {
+            config,
+            state,
+            recent: Mutex::new(vec![]),
+        }
+    }
+
+    /// Checks if the deletion can be applied right away. Otherwise, it's held in the state.
+    /// `tracked` is count of files in the versions list
+    pub fn allow(&self, d: Deletion, tracked: usize) -> Result<bool> {
+        let mut recent = self.recent.lock().unwrap();
+        recent.retain(|t| t.elapsed() < WINDOW);
+
+        let count = recent.len() + 1;
+        let too_many = (self.config.max_deletions != 0 && count > self.config.max_deletions)
+            || (self.config.max_deletions_percent != 0
+                && count > MIN_DELETIONS_FOR_PERCENT
+                && count * 100 > self.config.max_deletions_percent * tracked);
+
+        let held = self.state.update(|s| {
+            // Once something is held, everything else waits too
+            if !too_many && s.pending_deletions.is_empty() {
+                return None;
+            }
+
+            if !s.pending_deletions.iter().any(|p| p.id == d.id) {
+                s.pending_deletions.push(d);
+            }
+            Some(s.pending_deletions.len())
+        })?;
+
+        match held {
+            Some(pending) => {
+                if pending == 1 {
+                    eprintln!("Warn: Too many deletions at once. Deletions are paused until you confirm them.\nTip: run `ocean-drive deletions` to review them");
+                }
+                Ok(false)
+            }
+            None => {
+                recent.push(Instant::now());
+                Ok(true)
+            }
+        }
+    }
+
+    pub fn pending(&self) -> usize {
+        self.state
+            .load()
+            .map(|s| s.pending_deletions.len())
+            .unwrap_or(0)
+    }
+}
+
+/// Applies (`confirm` is true) or drops every pending deletion.
+/// Deletions that failed stay pending. Returns count of resolved deletions
+pub async fn resolve(
+    confirm: bool,
+    config: &Config,
+    client: &Client,
+    state: &StateStore,
+    versions: &Mutex<Versions>,
+) -> Result<usize> {
+    let pending = state.load()?.pending_deletions;
+    // Versions aren't held while the drive is changed, daemons keep running meanwhile
+    let snapshot = Versions::snapshot(versions)?;
+    let mut v_list = snapshot.clone();
+    let mut failed = vec![];
+
+    for d in &pending {
+        let res = match (confirm, &d.side) {
+            (true, Side::Local) => {
+                v_list.remove(&d.id);
+                if config.permanent_delete {
+                    client.detele_file(d.id.clone()).await
+                } else {
+                    client.trash_file(d.id.clone()).await.map(|_| ())
+                }
+            }
+            (true, Side::Remote) => {
+                v_list.remove(&d.id);
+                let p = Path::new(&d.version.path);
+                if p.exists() {
+                    RecycleBin::new(&d.root, config.recycle_bin.clone()).put(p)
+                } else {
+                    Ok(())
+                }
+            }
+            // The file is still in the drive, so it's downloaded again on the next sync
+            (false, Side::Local) => {
+                v_list.remove(&d.id);
+                Versions::invalidate(&d.version.parent_id, &mut v_list);
+                Ok(())
+            }
+            (false, Side::Remote) => client.untrash_file(d.id.clone()).await.map(|_| ()),
+        };
+
+        if let Err(e) = res {
+            eprintln!("Failed to resolve deletion of {:?}: {}", d.version.path, e);
+            failed.push(d.id.clone());
+        }
+    }
+
+    Versions::commit(versions, &snapshot, v_list)?;
+    state.update(|s| {
+        s.pending_deletions
+            .retain(|p| failed.contains(&p.id) || !pending.iter().any(|d| d.id == p.id))
+    })?;
+
+    Ok(pending.len() - failed.len())
+}
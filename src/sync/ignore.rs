@@ -0,0 +1,245 @@
+/*
+    Ignore rules with gitignore syntax. Rules come from `ignore` patterns in the config (they apply
+    to every sync pair) and from `.oceanignore` files, that can be placed in any synced directory.
+    Rules of deeper files win over upper ones, the last matching rule in a file wins.
+    Ignored items are neither uploaded nor downloaded.
+*/
+use crate::sync::util;
+use anyhow::{Context, Result};
+use regex::Regex;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+pub const FILE_NAME: &str = ".oceanignore";
+
+pub fn default_patterns() -> Vec<String> {
+    [".goutputstream-*", "*.swp", "*.swx", "*~", ".~lock.*#"]
+        .iter()
+        .map(|p| p.to_string())
+        .collect()
+}
+
+#[derive(Clone, Debug)]
+pub struct Rule {
+    pub pattern: String,
+    /// Where the rule is defined, e.g. `/home/user/Drive/.oceanignore:3`
+    pub source: String,
+    /// Negated rules (`!pattern`) bring back items ignored by previous rules
+    pub negated: bool,
+    /// Patterns are relative to this dir
+    base: PathBuf,
+    dir_only: bool,
+    regex: Regex,
+}
+
+impl Rule {
+    /// Returns `None` for empty lines and comments
+    fn parse(line: &str, base: &Path, source: String) -> Result<Option<Self>> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(None);
+        }
+
+        let mut p = line;
+        let negated = p.starts_with('!');
+        if negated {
+            p = &p[1..];
+        }
+        let dir_only = p.ends_with('/');
+        p = p.trim_end_matches('/');
+        // Pattern with a slash is relative to the base dir, otherwise it matches names at any depth
+        let anchored = p.contains('/');
+        p = p.trim_start_matches('/');
+
+        let mut re = String::from("^");
+        if !anchored {
+            re.push_str("(?:.*/)?");
+        }
+        re.push_str(&glob_to_regex(p));
+        re.push('$');
+
+        let regex = Regex::new(&re)
+            .with_context(|| format!("Invalid ignore pattern {:?} in {}", line, source))?;
+
+        Ok(Some(Self {
+            pattern: line.to_string(),
+            source,
+            negated,
+            base: base.to_path_buf(),
+            dir_only,
+            regex,
+        }))
+    }
+
+    fn matches(&self, p: &Path, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+
+        match p.strip_prefix(&self.base) {
+            Ok(rel) => self.regex.is_match(&rel.to_string_lossy()),
+            Err(_) => false,
+        }
+    }
+}
+
+fn glob_to_regex(glob: &str) -> String {
+    let mut re = String::new();
+    let chars: Vec<char> = glob.chars().collect();
+    let mut i = 0;
+
+    while i < chars.len() {
+        match chars[i] {
+            '*' if chars.get(i + 1) == Some(&'*') => {
+                if chars.get(i + 2) == Some(&'/') {
+                    re.push_str("(?:.*/)?");
+                    i += 1;
+                } else {
+                    re.push_str(".*");
+                }
+                i += 1;
+            }
+            '*' => re.push_str("[^/]*"),
+            '?' => re.push_str("[^/]"),
+            '[' => match chars[i..].iter().position(|c| *c == ']') {
+                Some(end) => {
+                    let class: String = chars[i + 1..i + end].iter().collect();
+                    re.push('[');
+                    re.push_str(&class.replacen('!', "^", 1).replace('\\', "\\\\"));
+                    re.push(']');
+                    i += end;
+                }
+                None => re.push_str("\\["),
+            },
+            '\\' if i + 1 < chars.len() => {
+                i += 1;
+                re.push_str(&regex::escape(&chars[i].to_string()));
+            }
+            c => re.push_str(&regex::escape(&c.to_string())),
+        }
+        i += 1;
+    }
+
+    re
+}
+
+/// Ignore rules of one sync pair
+pub struct IgnoreRules {
+    root: PathBuf,
+    global: Vec<Rule>,
+    /// Parsed `.oceanignore` of every checked dir, since every file of every scan is checked
+    files: Mutex<HashMap<PathBuf, CachedFile>>,
+}
+
+struct CachedFile {
+    /// Modification time and size of the file when it was parsed, `None` if there's no file
+    stamp: Option<(SystemTime, u64)>,
+    rules: Arc<Vec<Rule>>,
+}
+
+impl IgnoreRules {
+    pub fn new(root: &str, patterns: &[String]) -> Result<Self> {
+        let root = PathBuf::from(root);
+        let mut global = vec![];
+
+        for (i, p) in patterns.iter().enumerate() {
+            if let Some(rule) = Rule::parse(p, &root, format!("config.toml:ignore[{}]", i))? {
+                global.push(rule);
+            }
+        }
+
+        Ok(Self {
+            root,
+            global,
+            files: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn is_ignored(&self, p: &Path, is_dir: bool) -> bool {
+        match self.matching_rule(p, is_dir) {
+            Some(rule) => !rule.negated,
+            None => false,
+        }
+    }
+
+    /// Returns the rule that decides if the item is ignored. Items inside of ignored dirs are
+    /// ignored by the rule of the dir, like in git
+    pub fn matching_rule(&self, p: &Path, is_dir: bool) -> Option<Rule> {
+        let rel = p.strip_prefix(&self.root).ok()?;
+        let mut current = self.root.clone();
+        let mut rules = self.global.clone();
+        let mut components = rel.components().peekable();
+
+        while let Some(c) = components.next() {
+            // Rules of `.oceanignore` apply to everything below its dir
+            rules.extend(self.file_rules(&current).iter().cloned());
+            current.push(c);
+
+            let last = components.peek().is_none();
+            let rule = rules
+                .iter()
+                .rev()
+                .find(|r| r.matches(&current, !last || is_dir));
+
+            match rule {
+                Some(r) if !r.negated || last => return Some(r.clone()),
+                _ => {}
+            }
+        }
+
+        None
+    }
+
+    /// Rules of the `.oceanignore` in the dir. The file is parsed again only when it's changed
+    fn file_rules(&self, dir: &Path) -> Arc<Vec<Rule>> {
+        let path = dir.join(FILE_NAME);
+        let stamp = fs::metadata(&path)
+            .and_then(|m| Ok((m.modified()?, m.len())))
+            .ok();
+
+        let mut files = util::lock(&self.files);
+        match files.get(dir) {
+            Some(cached) if cached.stamp == stamp => Arc::clone(&cached.rules),
+            _ => {
+                let rules = Arc::new(match stamp {
+                    Some(_) => self.read_file(dir),
+                    None => vec![],
+                });
+                files.insert(
+                    dir.to_path_buf(),
+                    CachedFile {
+                        stamp,
+                        rules: Arc::clone(&rules),
+                    },
+                );
+                rules
+            }
+        }
+    }
+
+    /// Invalid or unreadable files are reported and skipped, so one typo won't stop the sync
+    fn read_file(&self, dir: &Path) -> Vec<Rule> {
+        let path = dir.join(FILE_NAME);
+        let content = match fs::read_to_string(&path) {
+            Ok(c) => c,
+            Err(_) => return vec![],
+        };
+
+        let mut rules = vec![];
+        for (i, line) in content.lines().enumerate() {
+            let source = format!("{}:{}", path.display(), i + 1);
+            match Rule::parse(line, dir, source) {
+                Ok(Some(rule)) => rules.push(rule),
+                Ok(None) => {}
+                Err(e) => eprintln!("Warn: {}", e),
+            }
+        }
+
+        rules
+    }
+}
@@ -1,6 +1,6 @@
 /*
     Handles local files updates (create, edit (also rename), delete)
-    It'll upload updated files to the remote and make sure conficts are resolved by creating copy for files
+    It'll upload updated files to the remote and make sure conficts are resolved with the configured strategy
 */
 extern crate notify;
 
@@ -9,53 +9,120 @@ use crate::{
     google_drive::{types::File, Client},
     setup::Config,
     sync::{
-        util,
-        versions::{Version, Versions, VersionsList},
+        self,
+        conflicts::{self, Change, Conflict, Resolution, Resolver},
+        deletions::{Deletion, DeletionGuard, Side},
+        ignore::IgnoreRules,
+        network::Network,
+        pairs::{self, Direction, SyncPair},
+        placeholders,
+        plan::{self, Operation},
+        progress::{Kind, Tracker},
+        queue::{Queue, QueuedOp},
+        root::RootCheck,
+        selective::Selection,
+        shutdown::Shutdown,
+        transfers, util,
+        versions::{Version, Versions, VersionsItem, VersionsList},
     },
 };
 use anyhow::{bail, Context, Result};
-use chrono;
+use futures::future::BoxFuture;
 use md5;
 use notify::{watcher, DebouncedEvent, RecursiveMode, Watcher};
 use std::{
+    collections::HashSet,
     fs,
     path::{Path, PathBuf},
-    sync::{mpsc::channel, Arc, Mutex, MutexGuard},
+    sync::{mpsc::channel, Arc, Mutex},
     time::Duration,
 };
+use tokio::sync::mpsc;
+
+/// How often the local root is checked while there are no events
+const ROOT_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Local change found by the scan
+#[derive(Debug)]
+enum LocalStep {
+    Upload(PathBuf),
+    Rename(PathBuf, PathBuf),
+    Delete(PathBuf),
+}
+
+/// Checked file that's sent by the transfer workers. Content is read again by the worker, so
+/// big trees are not kept in memory
+struct Upload {
+    path: PathBuf,
+    parent_id: String,
+    /// Synced file that gets the new content, a new one is created otherwise
+    id: Option<String>,
+    size: u64,
+}
 
 pub struct LocalDaemon {
-    client: Arc<Mutex<Client>>,
+    config: Config,
+    client: Arc<Client>,
     root_path: PathBuf,
     remote_root_id: String,
     versions: Arc<Mutex<Versions>>,
+    /// Directories of other sync pairs inside of the root, they are handled by their own daemons
+    nested: Vec<PathBuf>,
+    /// Container of the shared folders, it's not synced as a part of this pair
+    shared_container: Option<PathBuf>,
+    guard: Arc<DeletionGuard>,
+    root_check: RootCheck,
+    resolver: Arc<Resolver>,
+    ignore: IgnoreRules,
+    selection: Arc<Selection>,
+    direction: Direction,
+    queue: Queue,
+    network: Arc<Network>,
+    shutdown: Arc<Shutdown>,
+    progress: Arc<Tracker>,
 }
 
 impl LocalDaemon {
-    pub fn new(
-        config: Config,
-        client: Arc<Mutex<Client>>,
-        versions: Arc<Mutex<Versions>>,
-        remote_dir_id: String,
-    ) -> Result<Self> {
-        let local_root = Path::new(&config.local_dir).to_path_buf();
-
-        if !local_root.exists() {
-            bail!(
-                "Directory {:?} defined as the local syncing root for the app does not exist.",
-                &config.local_dir
-            );
-        }
+    pub fn new(ctx: &sync::Context, pair: SyncPair, nested: Vec<String>) -> Result<Self> {
+        let local_root = Path::new(&pair.local_dir).to_path_buf();
 
         return Ok(Self {
-            versions,
-            client,
+            root_check: RootCheck::new(pair.clone(), Arc::clone(&ctx.state)),
+            resolver: Arc::clone(&ctx.resolver),
+            ignore: IgnoreRules::new(&pair.local_dir, &ctx.config.ignore)?,
+            selection: Arc::clone(&ctx.selection),
+            config: ctx.config.clone(),
+            versions: Arc::clone(&ctx.versions),
+            client: Arc::clone(&ctx.client),
             root_path: local_root,
-            remote_root_id: remote_dir_id,
+            remote_root_id: pair.id,
+            direction: pair.direction,
+            queue: Queue::new(Arc::clone(&ctx.state)),
+            network: Arc::clone(&ctx.network),
+            shutdown: Arc::clone(&ctx.shutdown),
+            progress: Arc::clone(&ctx.progress),
+            nested: nested.iter().map(PathBuf::from).collect(),
+            shared_container: pairs::shared_container(&ctx.config),
+            guard: Arc::clone(&ctx.guard),
         });
     }
 
-    pub fn start(&self) -> Result<()> {
+    pub async fn start(&self) -> Result<()> {
+        while !self.shutdown.is_stopped() {
+            // Missing dir can't be watched, so wait until it's back
+            if !self.root_check.passes("Local") {
+                self.shutdown.wait(ROOT_CHECK_INTERVAL).await;
+                continue;
+            }
+
+            self.watch().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Handles local updates until the root stops passing the check or the app is stopped
+    async fn watch(&self) -> Result<()> {
         // Create a channel to receive the events.
         let (tx, rx) = channel();
         // Create a watcher object, delivering debounced events.
@@ -70,92 +137,444 @@ impl LocalDaemon {
             .watch(&self.root_path, RecursiveMode::Recursive)
             .context("Failed to start receiving updates from local directory.")?;
 
+        // Watcher sends events from its own thread, they are passed to the daemon task
+        let (events_tx, mut events) = mpsc::channel(64);
+        tokio::task::spawn_blocking(move || {
+            for e in rx {
+                if events_tx.blocking_send(e).is_err() {
+                    break;
+                }
+            }
+        });
+
         loop {
-            let event = rx
-                .recv()
-                .context("Unable to continue getting updates from local folder")?;
-            let client = util::lock_ref_when_free(&self.client);
-            let mut versions = util::lock_ref_when_free(&self.versions);
-            let mut v_list = versions.list()?;
-
-            let res = match &event {
-                DebouncedEvent::Create(f) => self.handle_write(&f, &client, &mut v_list),
-                DebouncedEvent::Write(f) => {
-                    if f.is_file() {
-                        return self.handle_write(&f, &client, &mut v_list);
+            let event = tokio::select! {
+                e = events.recv() => match e {
+                    Some(e) => Some(e),
+                    None => bail!("Unable to continue getting updates from local folder"),
+                },
+                _ = tokio::time::sleep(ROOT_CHECK_INTERVAL) => None,
+                _ = self.shutdown.stopped() => return Ok(()),
+            };
+            // Events from the unmounted root look like deletions of everything
+            if self.shutdown.is_stopped() || !self.root_check.passes("Local") {
+                return Ok(());
+            }
+
+            // Changes are saved in the queue first, so they are not lost if uploading fails
+            if let Some(op) = event
+                .and_then(|e| self.filter_event(e))
+                .and_then(|e| QueuedOp::from_event(&e))
+            {
+                self.queue.push(op)?;
+            }
+            self.drain_queue().await?;
+        }
+    }
+
+    /// Sends changes from the queue that are due to the drive
+    async fn drain_queue(&self) -> Result<()> {
+        let due = self
+            .queue
+            .due(|p| p.starts_with(&self.root_path) && !self.is_nested(p))?;
+        // Changes wait in the queue while offline
+        if due.is_empty() || !self.network.is_online() {
+            return Ok(());
+        }
+
+        let client = &self.client;
+        // Uploads run without holding versions, so the remote daemon isn't blocked meanwhile
+        let snapshot = Versions::snapshot(&self.versions)?;
+        let mut v_list = snapshot.clone();
+        // Paths whose change failed. Later changes of them (or of their children) would be made
+        // out of order, so they stay in the queue until the failed one goes through
+        let mut failed: HashSet<PathBuf> = HashSet::new();
+
+        for op in due {
+            // The rest stays in the queue until the next start
+            if self.shutdown.is_stopped() {
+                break;
+            }
+
+            let paths: Vec<PathBuf> = op.paths().into_iter().map(PathBuf::from).collect();
+            if paths
+                .iter()
+                .any(|p| failed.iter().any(|f| p.starts_with(f)))
+            {
+                failed.extend(paths);
+                continue;
+            }
+
+            match self.process(&op, client, &mut v_list).await {
+                Ok(_) => self.queue.done(&op)?,
+                Err(e) => {
+                    eprintln!("{}\nInfo: The change is queued and will be retried.\nTip: run `ocean-drive queue` to see pending changes", e);
+                    self.queue.failed(&op, &e)?;
+                    failed.extend(paths);
+                    // Other changes would fail the same way without the network
+                    if !self.network.check().await {
+                        break;
                     }
-                    Ok(())
                 }
-                DebouncedEvent::Rename(old, new) => {
-                    let parent = new.parent().with_context(|| {
-                        format!(
-                            "Failed to get file parent on renamed file {:?}",
-                            new.display()
+            }
+        }
+
+        Versions::commit(&self.versions, &snapshot, v_list)
+    }
+
+    async fn process(
+        &self,
+        op: &QueuedOp,
+        client: &Client,
+        v_list: &mut VersionsList,
+    ) -> Result<()> {
+        match op {
+            QueuedOp::Write { path } => {
+                self.handle_write(&PathBuf::from(path), client, v_list)
+                    .await
+            }
+            QueuedOp::Rename { from, to } => {
+                let new = PathBuf::from(to);
+                let parent = new.parent().with_context(|| {
+                    format!("Failed to get file parent on renamed file {:?}", to)
+                })?;
+
+                self.handle_rename(
+                    PathBuf::from(from),
+                    new.clone(),
+                    parent.to_path_buf(),
+                    client,
+                    v_list,
+                )
+                .await
+            }
+            QueuedOp::Delete { path } => {
+                // Created again after the deletion, a queued write uploads it
+                if fs::symlink_metadata(path).is_ok() {
+                    return Ok(());
+                }
+                self.handle_delete(PathBuf::from(path), client, v_list)
+                    .await
+            }
+        }
+    }
+
+    /// Compares the whole local tree with versions, since the watcher only knows about changes
+    /// made while it runs. The plan is applied only if `apply` is set.
+    /// Returns operations of the plan and errors of single items
+    pub async fn scan(&self, apply: bool) -> Result<(Vec<Operation>, Vec<String>)> {
+        let client = &self.client;
+        let snapshot = Versions::snapshot(&self.versions)?;
+        let mut v_list = snapshot.clone();
+        let mut errors = vec![];
+        let mut ops = vec![];
+        let mut uploads = vec![];
+
+        for step in self.plan(&v_list, &mut errors) {
+            match self.operation(&step, client, &v_list).await {
+                Ok(op) => ops.extend(op),
+                Err(e) => {
+                    errors.push(format!("Failed to check {:?}: {}", step, e));
+                    continue;
+                }
+            }
+            if !apply {
+                continue;
+            }
+
+            let res = match &step {
+                LocalStep::Upload(p) => {
+                    self.prepare_write(p, client, &mut v_list, &mut uploads)
+                        .await
+                }
+                LocalStep::Rename(old, new) => match new.parent() {
+                    Some(parent) => {
+                        self.handle_rename(
+                            old.clone(),
+                            new.clone(),
+                            parent.to_path_buf(),
+                            client,
+                            &mut v_list,
                         )
-                    })?;
-
-                    self.handle_rename(
-                        old.to_path_buf(),
-                        new.to_path_buf(),
-                        parent.to_path_buf(),
-                        &client,
-                        &mut v_list,
-                    )
+                        .await
+                    }
+                    None => Ok(()),
+                },
+                LocalStep::Delete(p) => self.handle_delete(p.clone(), client, &mut v_list).await,
+            };
+            if let Err(e) = res {
+                errors.push(format!("Failed to sync {:?}: {}", step, e));
+            }
+        }
+        if let Err(e) = self.send(uploads, client, &mut v_list).await {
+            errors.push(e.to_string());
+        }
+
+        // Dry runs leave versions as they are
+        if apply {
+            Versions::commit(&self.versions, &snapshot, v_list)?;
+        }
+        Ok((ops, errors))
+    }
+
+    /// Finds local changes since the last sync. Nothing is changed here
+    fn plan(&self, v_list: &VersionsList, errors: &mut Vec<String>) -> Vec<LocalStep> {
+        let mut changed = vec![];
+        self.plan_dir(&self.root_path, v_list, &mut changed, errors);
+
+        // Children of a removed directory go with it
+        let mut missing = v_list
+            .iter()
+            .filter(|(_, v)| {
+                let p = Path::new(&v.path);
+                p.starts_with(&self.root_path)
+                    && v.shortcut.is_none()
+                    && !self.is_ignored(p)
+                    && fs::symlink_metadata(p).is_err()
+            })
+            .map(|(_, v)| (PathBuf::from(&v.path), v.md5.clone()))
+            .collect::<Vec<_>>();
+        missing.sort();
+
+        let mut steps = vec![];
+        let mut removed: Vec<PathBuf> = vec![];
+        for (p, md5) in missing {
+            if removed.iter().any(|r| p.starts_with(r)) {
+                continue;
+            }
+            removed.push(p.clone());
+
+            // New file with the same content in the same dir or with the same name is the
+            // missing one moved
+            let moved = changed.iter().position(|(new, is_new, hash)| {
+                *is_new
+                    && hash.is_some()
+                    && hash == &md5
+                    && (new.file_name() == p.file_name() || new.parent() == p.parent())
+            });
+            match moved {
+                Some(i) => {
+                    let (new, _, _) = changed.remove(i);
+                    steps.push(LocalStep::Rename(p, new));
+                }
+                None => steps.push(LocalStep::Delete(p)),
+            }
+        }
+
+        // Deletions go last, so nothing is lost if uploads fail
+        let mut uploads = changed
+            .into_iter()
+            .map(|(p, _, _)| LocalStep::Upload(p))
+            .collect::<Vec<_>>();
+        uploads.append(&mut steps);
+        uploads
+    }
+
+    /// Collects new and changed items: (path, is it untracked, md5 of files)
+    fn plan_dir(
+        &self,
+        dir: &Path,
+        v_list: &VersionsList,
+        changed: &mut Vec<(PathBuf, bool, Option<String>)>,
+        errors: &mut Vec<String>,
+    ) {
+        let entries = match fs::read_dir(dir) {
+            Ok(e) => e,
+            Err(e) => {
+                errors.push(format!("Failed to read {:?}: {}", dir.display(), e));
+                return;
+            }
+        };
+
+        for entry in entries.flatten() {
+            let p = entry.path();
+            // Symlinks are made for shortcuts
+            let is_link = entry.file_type().map(|t| t.is_symlink()).unwrap_or(true);
+            if is_link || self.is_ignored(&p) || placeholders::read(&p).is_some() {
+                continue;
+            }
+
+            let local = Versions::find_item_by_path(p.clone(), v_list);
+            if p.is_dir() {
+                if local.is_some() {
+                    self.plan_dir(&p, v_list, changed, errors);
+                } else if !self.is_read_only(&p, v_list) {
+                    changed.push((p, true, None));
                 }
-                DebouncedEvent::Remove(f) => {
-                    self.handle_delete(f.to_path_buf(), &client, &mut v_list)
+                continue;
+            }
+
+            let hash = match files::read_bytes(p.clone()) {
+                Ok(content) => format!("{:x}", md5::compute(&content)),
+                Err(e) => {
+                    errors.push(format!("Failed to read {:?}: {}", p.display(), e));
+                    continue;
                 }
-                _ => Ok(()),
             };
+            // Permissions of read-only files differ from the drive, they are never uploaded
+            let is_same = local.as_ref().is_some_and(|l| {
+                l.1.md5.as_ref() == Some(&hash)
+                    && (l.1.read_only || l.1.mode == util::local_mode(&p))
+            });
+            if !is_same && !self.is_read_only(&p, v_list) {
+                changed.push((p, local.is_none(), Some(hash)));
+            }
+        }
+    }
 
-            if let Err(e) = res {
-                eprintln!("{}", e);
+    /// Operation of the plan for the step. Remote content is checked for uploads, to tell
+    /// conflicts apart. Read-only items are only forgotten when they are removed, so they make no
+    /// operation
+    async fn operation(
+        &self,
+        step: &LocalStep,
+        client: &Client,
+        v_list: &VersionsList,
+    ) -> Result<Option<Operation>> {
+        let p = match step {
+            LocalStep::Upload(p) => p,
+            LocalStep::Rename(old, new) => {
+                return Ok(Some(Operation::Rename {
+                    from: old.display().to_string(),
+                    to: new.display().to_string(),
+                    side: plan::Side::Remote,
+                }))
             }
+            LocalStep::Delete(p) => {
+                let read_only = Versions::find_item_by_path(p.clone(), v_list)
+                    .map(|v| v.1.read_only)
+                    .unwrap_or(false);
+                return Ok(Some(Operation::Delete {
+                    path: p.display().to_string(),
+                    side: plan::Side::Remote,
+                })
+                .filter(|_| !read_only));
+            }
+        };
 
-            versions.save(v_list)?;
+        let path = p.display().to_string();
+        if p.is_dir() {
+            return Ok(Some(Operation::Upload { path }));
+        }
 
-            drop(versions);
-            drop(client);
+        let local = Versions::find_item_by_path(p.clone(), v_list);
+        let remote = match &local {
+            Some(local) => client.get_file(&local.0).await?,
+            None => {
+                let parent_id = p
+                    .parent()
+                    .and_then(|parent| Versions::find_item_by_path(parent.to_path_buf(), v_list))
+                    .map(|v| v.0)
+                    .unwrap_or_else(|| self.remote_root_id.clone());
+                client
+                    .get_file_by_name(&self.get_file_name(p)?, Some(parent_id))
+                    .await?
+            }
         }
+        .filter(|r| !r.trashed.unwrap_or(false));
+
+        let hash = format!("{:x}", md5::compute(files::read_bytes(p.clone())?));
+        let base = local.as_ref().and_then(|l| l.1.md5.as_ref());
+        let change = match remote {
+            Some(r) => conflicts::detect(base, Some(&hash), r.md5.as_ref()),
+            None => Change::Local,
+        };
+
+        Ok(Some(match change {
+            Change::Remote | Change::Both => Operation::Conflict { path },
+            _ => Operation::Upload { path },
+        }))
     }
 
-    // TODO: Move to some utils mod
-    /// Moves the file into the path prefixed with timestamp to differ it from other copies
-    /// Returns `PathBuf` with new file
-    /// This is an util function
-    fn create_local_copy(&self, f: &PathBuf) -> Result<PathBuf> {
-        let parent_path = f.parent();
-        let name = self.get_file_name(&f)?;
+    /// Filters out changes inside of the local trash, ignored items and directories of other sync
+    /// pairs. Returns the event that should be handled instead of the original one
+    fn filter_event(&self, event: DebouncedEvent) -> Option<DebouncedEvent> {
+        match event {
+            DebouncedEvent::Create(ref f)
+            | DebouncedEvent::Write(ref f)
+            | DebouncedEvent::Chmod(ref f)
+            | DebouncedEvent::Remove(ref f) => {
+                if self.is_ignored(f) {
+                    return None;
+                }
+                Some(event)
+            }
+            DebouncedEvent::Rename(old, new) => {
+                // Files are moved to the local trash by the remote daemon, it already took care of them
+                if util::is_internal(&self.root_path, &new)
+                    || (self.is_nested(&old) && self.is_nested(&new))
+                {
+                    return None;
+                }
 
-        // We start path for the new file with it's parent
-        let mut new_path = parent_path
-            .clone()
-            .unwrap_or(&Path::new("")) // It's root if it's no parent
-            .to_path_buf()
-            .display()
-            .to_string();
+                match (self.is_ignored(&old), self.is_ignored(&new)) {
+                    (true, true) => None,
+                    // Moved from the place we don't track, so it's just a new file for us
+                    (true, false) => Some(DebouncedEvent::Create(new)),
+                    // Moved to the place we don't track, so it's not synced anymore
+                    (false, true) => Some(DebouncedEvent::Remove(old)),
+                    (false, false) => Some(DebouncedEvent::Rename(old, new)),
+                }
+            }
+            _ => Some(event),
+        }
+    }
+
+    fn is_ignored(&self, p: &Path) -> bool {
+        util::is_internal(&self.root_path, p)
+            || self.is_nested(p)
+            || self.in_shared_container(p)
+            || self.ignore.is_ignored(p, p.is_dir())
+            || self.selection.is_excluded_path(&self.root_path, p)
+    }
+
+    /// Checks if the path belongs to the directory of another sync pair
+    fn is_nested(&self, p: &Path) -> bool {
+        self.nested.iter().any(|n| p.starts_with(n))
+    }
+
+    /// Checks if the path is the container of shared folders or anything inside of it
+    fn in_shared_container(&self, p: &Path) -> bool {
+        self.shared_container
+            .as_ref()
+            .is_some_and(|d| p.starts_with(d))
+    }
 
-        // Then we add slash because parent is a directory
-        new_path.push_str("/");
+    /// Checks that the item (or the closest synced directory above it) can be changed in the drive
+    fn is_read_only(&self, f: &Path, v_list: &VersionsList) -> bool {
+        let item = f
+            .ancestors()
+            .take_while(|p| p.starts_with(&self.root_path))
+            .find_map(|p| Versions::find_item_by_path(p.to_path_buf(), v_list));
 
-        // New file name is built using 2 parts: time tag and the old name
-        let t = chrono::Local::now();
-        let mut new_name = t.format("[%d.%m.%y %H:%M:%S] ").to_string();
-        new_name.push_str(&name);
+        if let Some((_, v)) = item {
+            if v.read_only {
+                println!(
+                    "Warn: {:?} is read-only in the drive, local changes won't be uploaded",
+                    f.display()
+                );
+                return true;
+            }
+        }
 
-        // Finish building new file path
-        new_path.push_str(&new_name);
+        false
+    }
 
-        // Move our file to the new path. Old path will be overwriten by remote daemon
+    /// Moves the directory to the path built with the naming template for conflict copies
+    /// Returns `PathBuf` with new directory
+    fn create_local_copy(&self, f: &PathBuf) -> Result<PathBuf> {
+        let new_path = conflicts::copy_path(&self.config.conflicts.copy_name, f);
+
+        // Move our dir to the new path. Old path will be overwriten by remote daemon
         fs::rename(f, &new_path).with_context(|| {
             format!(
-                "Error creating local copy {:?} for the file {:?},",
-                new_path,
+                "Error creating local copy {:?} for the directory {:?},",
+                new_path.display(),
                 f.display()
             )
         })?;
 
-        Ok(Path::new(&new_path).to_path_buf())
+        Ok(new_path)
     }
 
     /// Retrieves a readable file name
@@ -178,23 +597,50 @@ impl LocalDaemon {
     }
 
     /// Handles logic for new and updated files
-    fn handle_write(
+    async fn handle_write(
+        &self,
+        f: &Path,
+        client: &Client,
+        v_list: &mut VersionsList,
+    ) -> Result<()> {
+        let mut uploads = vec![];
+        self.prepare_write(f, client, v_list, &mut uploads).await?;
+        self.send(uploads, client, v_list).await
+    }
+
+    /// Creates new directories and adds contents that should be sent to `uploads`
+    async fn prepare_write(
         &self,
-        f: &PathBuf,
-        client: &MutexGuard<Client>,
+        f: &Path,
+        client: &Client,
         v_list: &mut VersionsList,
+        uploads: &mut Vec<Upload>,
     ) -> Result<()> {
-        if !f.exists() {
+        if !f.exists() || self.is_read_only(f, v_list) {
             return Ok(());
         }
 
         if let Some(parent) = f.parent() {
             // Get file info from versions file
             if f.is_file() {
-                self.upload_file(f.to_path_buf(), parent.to_path_buf(), &client, v_list)?;
+                self.upload_file(
+                    f.to_path_buf(),
+                    parent.to_path_buf(),
+                    client,
+                    v_list,
+                    uploads,
+                )
+                .await?;
             }
             if f.is_dir() {
-                self.upload_dir(f.to_path_buf(), parent.to_path_buf(), &client, v_list)?;
+                self.upload_dir(
+                    f.to_path_buf(),
+                    parent.to_path_buf(),
+                    client,
+                    v_list,
+                    uploads,
+                )
+                .await?;
             }
             return Ok(());
         }
@@ -202,12 +648,12 @@ impl LocalDaemon {
         bail!("Failed to get file parent: {:?}", f.display());
     }
 
-    fn handle_rename(
+    async fn handle_rename(
         &self,
         old_file: PathBuf,
         new_file: PathBuf,
         parent: PathBuf,
-        client: &MutexGuard<Client>,
+        client: &Client,
         v_list: &mut VersionsList,
     ) -> Result<()> {
         if !new_file.exists() || !parent.exists() {
@@ -221,7 +667,11 @@ impl LocalDaemon {
             .to_string()
             .starts_with(&self.root_path.display().to_string())
         {
-            return self.handle_delete(old_file, client, v_list);
+            return self.handle_delete(old_file, client, v_list).await;
+        }
+
+        if self.is_read_only(&old_file, v_list) {
+            return Ok(());
         }
 
         // Get information about previous location of the file
@@ -239,7 +689,10 @@ impl LocalDaemon {
 
             let new_name = self.get_file_name(&new_file)?;
 
-            let updated = client.rename_file(info.0, &new_name, parent_id.clone())?;
+            let updated = client
+                .rename_file(info.0, &new_name, parent_id.clone())
+                .await?;
+            let mode = updated.mode().or(info.1.mode);
 
             // Save the new version (then remote daemon won't update this file again since it's
             // already in sync with the cloud)
@@ -249,121 +702,168 @@ impl LocalDaemon {
                 version: updated.version.unwrap_or(String::from("1")),
                 is_folder: false,
                 parent_id,
+                read_only: false,
+                shortcut: None,
+                mode,
             };
 
             v_list.insert(updated.id.unwrap(), new_v);
         } else {
             // If file was not on versions list earlier, this file is completly new so handle it like a
             // new file
-            self.handle_write(&new_file, client, v_list)?;
+            self.handle_write(&new_file, client, v_list).await?;
         }
 
         Ok(())
     }
 
-    // Love this function, so small :+)
-    fn handle_delete(
+    async fn handle_delete(
         &self,
         f: PathBuf,
-        client: &MutexGuard<Client>,
+        client: &Client,
         v_list: &mut VersionsList,
     ) -> Result<()> {
         if let Some(v) = Versions::find_item_by_path(f, v_list) {
+            let deletion = Deletion {
+                id: v.0.clone(),
+                side: Side::Local,
+                root: self.root_path.display().to_string(),
+                version: v.1.clone(),
+            };
+
+            // Held deletions keep their versions until the user decides what to do
+            if !v.1.read_only && !self.guard.allow(deletion, v_list.len())? {
+                return Ok(());
+            }
+
             v_list.remove(&v.0);
-            client.detele_file(v.0)?;
+            self.resolver.bases().remove(&v.0);
+            if v.1.read_only {
+                return Ok(());
+            }
+
+            if self.config.permanent_delete {
+                client.detele_file(v.0).await?;
+            } else {
+                client.trash_file(v.0).await?;
+            }
         }
 
         Ok(())
     }
 
     // Recursively upload every file or create a new dir
-    fn upload_dir(
-        &self,
+    fn upload_dir<'a>(
+        &'a self,
         mut dir: PathBuf,
         parent: PathBuf,
-        client: &MutexGuard<Client>,
-        v_list: &mut VersionsList,
-    ) -> Result<()> {
-        // Don't upload already synced dir
-        if let Some(_) = Versions::find_item_by_path(dir.clone(), v_list) {
-            return Ok(());
-        }
+        client: &'a Client,
+        v_list: &'a mut VersionsList,
+        uploads: &'a mut Vec<Upload>,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            // Don't upload already synced dir
+            if let Some(_) = Versions::find_item_by_path(dir.clone(), v_list) {
+                return Ok(());
+            }
 
-        let name = self.get_file_name(&dir)?;
+            let name = self.get_file_name(&dir)?;
 
-        let parent_id = if let Some(info) = Versions::find_item_by_path(parent.clone(), v_list) {
-            info.0
-        } else {
-            self.remote_root_id.clone()
-        };
+            let parent_id = if let Some(info) = Versions::find_item_by_path(parent.clone(), v_list)
+            {
+                info.0
+            } else {
+                self.remote_root_id.clone()
+            };
 
-        if let Some(remote) = client.get_file_by_name(&name, Some(parent_id.clone()))? {
-            if !remote.trashed.unwrap_or(false) {
-                let v = Versions::find_item_by_path(dir.clone(), v_list);
-                // Creates a copy of the local directory if remote and local are different
-                // or if there's no version in the versions file but we still get it untrashed in
-                // the cloud
-                if v.is_none() || v.as_ref().unwrap().1.version != remote.version.unwrap() {
-                    dir = self.create_local_copy(&dir).with_context(
-                        || format!(" Was unable to create a local copy for the directory {:?}. This dir won't be uploaded to drive.", dir.display()))?;
-                    // Remove version if exists
-                    if v.is_some() {
-                        v_list.remove(&v.unwrap().0);
+            if let Some(remote) = client
+                .get_file_by_name(&name, Some(parent_id.clone()))
+                .await?
+            {
+                if !remote.trashed.unwrap_or(false) {
+                    let v = Versions::find_item_by_path(dir.clone(), v_list);
+                    // Creates a copy of the local directory if remote and local are different
+                    // or if there's no version in the versions file but we still get it untrashed in
+                    // the cloud
+                    if v.is_none() || v.as_ref().unwrap().1.version != remote.version.unwrap() {
+                        dir = self.create_local_copy(&dir).with_context(
+                            || format!(" Was unable to create a local copy for the directory {:?}. This dir won't be uploaded to drive.", dir.display()))?;
+                        // Remove version if exists
+                        if v.is_some() {
+                            v_list.remove(&v.unwrap().0);
+                        }
                     }
                 }
             }
-        }
 
-        let new = client.create_dir(&self.get_file_name(&dir)?, parent_id.clone())?;
+            let new = client
+                .create_dir(&self.get_file_name(&dir)?, parent_id.clone())
+                .await?;
 
-        let v = Version {
-            version: new.version.unwrap_or(String::from("1")),
-            md5: None,
-            path: dir.display().to_string(),
-            is_folder: true,
-            parent_id,
-        };
+            let v = Version {
+                version: new.version.unwrap_or(String::from("1")),
+                md5: None,
+                path: dir.display().to_string(),
+                is_folder: true,
+                parent_id,
+                read_only: false,
+                shortcut: None,
+                mode: None,
+            };
 
-        v_list.insert(new.id.unwrap(), v);
+            v_list.insert(new.id.unwrap(), v);
 
-        // After we create a dir, we should upload all of it's children
-        for f in fs::read_dir(&dir)? {
-            let p = f
-                .with_context(|| {
-                    format!(
-                        "Unable to read directory enrty when uploading directory {:?}",
-                        dir.display()
-                    )
-                })?
-                .path();
+            // After we create a dir, we should upload all of it's children
+            for f in fs::read_dir(&dir)? {
+                let p = f
+                    .with_context(|| {
+                        format!(
+                            "Unable to read directory enrty when uploading directory {:?}",
+                            dir.display()
+                        )
+                    })?
+                    .path();
 
-            if p.is_dir() {
-                if let Err(e) = self.upload_dir(p.clone(), dir.clone(), client, v_list) {
-                    eprintln!("Failed to upload directory {:?}\nCause: {}", p.display(), e);
+                if self.is_ignored(&p) {
+                    continue;
                 }
-            } else if p.is_file() {
-                if let Err(e) = self.upload_file(p.clone(), dir.clone(), client, v_list) {
-                    eprintln!("Failed to upload file {:?}\nCause: {}", p.display(), e);
+
+                if p.is_dir() {
+                    if let Err(e) = self
+                        .upload_dir(p.clone(), dir.clone(), client, v_list, uploads)
+                        .await
+                    {
+                        eprintln!("Failed to upload directory {:?}\nCause: {}", p.display(), e);
+                    }
+                } else if p.is_file() {
+                    if let Err(e) = self
+                        .upload_file(p.clone(), dir.clone(), client, v_list, uploads)
+                        .await
+                    {
+                        eprintln!("Failed to upload file {:?}\nCause: {}", p.display(), e);
+                    }
                 }
             }
-        }
 
-        Ok(())
+            Ok(())
+        })
     }
 
-    /// Uploading completly new file
-    fn upload_file(
+    /// Checks the new or changed file and adds its content to `uploads`
+    async fn upload_file(
         &self,
-        mut f: PathBuf,
+        f: PathBuf,
         parent: PathBuf,
-        client: &MutexGuard<Client>,
+        client: &Client,
         v_list: &mut VersionsList,
+        uploads: &mut Vec<Upload>,
     ) -> Result<()> {
-        if f.is_dir() {
+        // Placeholders of online-only files have no real content
+        if f.is_dir() || placeholders::read(&f).is_some() {
             return Ok(());
         }
 
-        let local = Versions::find_item_by_path(f.clone(), v_list);
+        let mut local = Versions::find_item_by_path(f.clone(), v_list);
         let content = files::read_bytes(f.to_path_buf())?;
         let hash = format!("{:x}", md5::compute(&content));
 
@@ -373,7 +873,8 @@ impl LocalDaemon {
             // save the version. So when we meet write, it does not always mean the content was
             // updated
             if local.1.md5.as_ref().unwrap_or(&String::from("")) == &hash {
-                return Ok(());
+                // Content is synced, but permissions could be changed with chmod
+                return self.update_mode(&f, local, client, v_list).await;
             }
         }
 
@@ -385,42 +886,203 @@ impl LocalDaemon {
             self.remote_root_id.clone()
         };
 
-        let remote_file = client.get_file_by_name(&name, Some(parent_id.clone()))?;
+        // Synced files are found by id, since they could be renamed in the drive
+        let remote_file = match &local {
+            Some(local) => client.get_file(&local.0).await?,
+            None => {
+                client
+                    .get_file_by_name(&name, Some(parent_id.clone()))
+                    .await?
+            }
+        }
+        .filter(|r| !r.trashed.unwrap_or(false));
+
+        if let (Some(l), None) = (&local, &remote_file) {
+            // Trashed files are handled by the remote daemon, but it doesn't run for backups, so
+            // the file is uploaded again as a new one
+            if self.direction != Direction::UploadOnly {
+                return Ok(());
+            }
+            v_list.remove(&l.0);
+            self.resolver.bases().remove(&l.0);
+            local = None;
+        }
 
         // Check if the file on the remote is different from what we have on local
         if let Some(remote_file) = remote_file {
-            if !remote_file.trashed.unwrap() {
-                if let Some(md5) = remote_file.md5 {
-                    if md5 == hash {
-                        // Since file was new and it's already in the cloud, there's nothing to do
-                        return Ok(());
+            let base = local.as_ref().and_then(|l| l.1.md5.as_ref());
+
+            match conflicts::detect(base, Some(&hash), remote_file.md5.as_ref()) {
+                // Since file is already in the cloud, there's nothing to do
+                Change::None => return Ok(()),
+                Change::Local => {}
+                Change::Remote | Change::Both => {
+                    let c = Conflict {
+                        id: remote_file.id.unwrap(),
+                        path: f.display().to_string(),
+                        parent_id,
+                        base_md5: base.cloned(),
+                        local_md5: hash,
+                        remote_md5: remote_file.md5,
+                        remote_modified: remote_file.modified_time,
+                        read_only: false,
+                    };
+
+                    // Local content is the one that matters for backups
+                    if self.direction == Direction::UploadOnly {
+                        return self
+                            .resolver
+                            .apply(Resolution::KeepLocal, &c, client, v_list)
+                            .await;
                     }
-                    f = self.create_local_copy(&f)?;
+                    return self.resolver.resolve(&c, client, v_list).await.map(|_| ());
+                }
+            }
+        }
+
+        uploads.push(Upload {
+            path: f,
+            parent_id,
+            id: local.map(|l| l.0),
+            size: content.len() as u64,
+        });
+
+        Ok(())
+    }
+
+    /// Saves new permissions of the synced file in the drive, the content isn't sent again
+    async fn update_mode(
+        &self,
+        f: &Path,
+        local: &VersionsItem,
+        client: &Client,
+        v_list: &mut VersionsList,
+    ) -> Result<()> {
+        let mode = util::local_mode(f);
+        if mode.is_none() || mode == local.1.mode {
+            return Ok(());
+        }
+
+        let attributes = util::local_attributes(f, self.config.keep_owner)?;
+        let updated = client
+            .update_attributes(local.0.clone(), attributes)
+            .await?;
+        if let Some(v) = v_list.get_mut(&local.0) {
+            v.mode = mode;
+            // Otherwise the remote daemon would see the new version as a change
+            if let Some(version) = updated.version {
+                v.version = version;
+            }
+        }
+
+        println!(
+            "Info: Permissions of {:?} are updated in the drive",
+            f.display()
+        );
+        Ok(())
+    }
+
+    /// Uploads contents on the transfer workers and records versions of the uploaded files
+    async fn send(
+        &self,
+        uploads: Vec<Upload>,
+        client: &Client,
+        v_list: &mut VersionsList,
+    ) -> Result<()> {
+        let count = uploads.len();
+        let jobs = uploads.into_iter().map(|u| (u.size, u)).collect();
+        let results = transfers::run(
+            self.config.transfers.uploads,
+            jobs,
+            &self.shutdown,
+            |u| async move {
+                let res = self.transfer(&u, client).await;
+                (u, res)
+            },
+        )
+        .await;
+
+        let mut errors = vec![];
+        // Changes stay in the queue, so they are uploaded on the next start
+        if results.len() < count {
+            errors.push(format!(
+                "{} upload(s) are skipped since the app is stopping",
+                count - results.len()
+            ));
+        }
+        for (u, res) in results {
+            let (new, base) = match res {
+                Ok(r) => r,
+                Err(e) => {
+                    errors.push(format!("Failed to upload {:?}: {}", u.path.display(), e));
+                    continue;
                 }
+            };
+
+            // Add information about the file to the versions file so it won't be proccessed twice
+            if let Some(old) = &u.id {
+                v_list.remove(old);
             }
+            let mode = new.mode();
+            let id = new.id.unwrap();
+            if let Some(base) = base {
+                self.resolver.bases().save(&id, &u.path, &base)?;
+            }
+            v_list.insert(
+                id,
+                Version {
+                    md5: new.md5,
+                    path: u.path.display().to_string(),
+                    version: new.version.unwrap_or(String::from("1")),
+                    is_folder: false,
+                    parent_id: u.parent_id,
+                    read_only: false,
+                    shortcut: None,
+                    mode,
+                },
+            );
         }
 
-        let new: File;
+        if !errors.is_empty() {
+            bail!(errors.join("\n"));
+        }
+        Ok(())
+    }
 
-        if let Some(local) = local {
-            // Remove old version from the versions list
-            v_list.remove(&local.0);
-            // And the upload the new on into the cloud
-            new = client.update_file(local.0, content)?;
+    /// Sends the current content of the file. Returns the new file and the content if it's kept
+    /// as the merge base
+    async fn transfer(&self, u: &Upload, client: &Client) -> Result<(File, Option<Vec<u8>>)> {
+        let content = files::read_bytes(u.path.clone())?;
+        // Synced content is the base for merging future changes
+        let base = if self.resolver.bases().applies(&u.path) {
+            Some(content.clone())
         } else {
-            new = client.upload_file(&self.get_file_name(&f)?, parent_id.clone(), content)?;
-        }
-        // Add information about the file to the versions file so it won't be proccessed twice
-        let new_v = Version {
-            md5: new.md5,
-            path: f.display().to_string(),
-            version: new.version.unwrap_or(String::from("1")),
-            is_folder: false,
-            parent_id,
+            None
         };
 
-        v_list.insert(new.id.unwrap(), new_v);
+        let attributes = util::local_attributes(&u.path, self.config.keep_owner)?;
 
-        Ok(())
+        let tracked = self
+            .progress
+            .start(&u.path, Kind::Upload, content.len() as u64);
+        let new = match &u.id {
+            Some(id) => {
+                client
+                    .update_with_progress(id.clone(), content, attributes, tracked.progress())
+                    .await?
+            }
+            None => {
+                client
+                    .upload_with_progress(
+                        &self.get_file_name(&u.path)?,
+                        u.parent_id.clone(),
+                        content,
+                        attributes,
+                        tracked.progress(),
+                    )
+                    .await?
+            }
+        };
+        Ok((new, base))
     }
 }
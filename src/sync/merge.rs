@@ -0,0 +1,257 @@
+/*
+    Three-way merge of text files. Last synced content of files that opted in (`merge` globs in
+    the conflicts config) is kept in ~/.config/ocean-drive/bases as a merge base. When both sides
+    changed the file, local and remote changes are merged line by line, unless they touch the
+    same lines.
+*/
+use anyhow::{Context, Result};
+use regex::Regex;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Finding common lines takes `lines * lines` memory for the changed part of the file, so bigger
+/// changes are not merged. It's about 4 MB
+const MAX_MERGE_CELLS: usize = 1_000_000;
+
+pub struct MergeBases {
+    dir: PathBuf,
+    patterns: Vec<Regex>,
+}
+
+impl MergeBases {
+    pub fn new(dir: PathBuf, globs: &[String]) -> Result<Self> {
+        let patterns = globs
+            .iter()
+            .map(|g| {
+                Regex::new(&glob_to_regex(g))
+                    .with_context(|| format!("Invalid merge pattern {:?} in the config", g))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self { dir, patterns })
+    }
+
+    /// Checks if the file opted in for merging by its name
+    pub fn applies(&self, p: &Path) -> bool {
+        let name = match p.file_name() {
+            Some(n) => n.to_string_lossy(),
+            None => return false,
+        };
+
+        self.patterns.iter().any(|r| r.is_match(&name))
+    }
+
+    /// Remembers synced content of the file if it opted in for merging
+    pub fn save(&self, id: &str, p: &Path, content: &[u8]) -> Result<()> {
+        if !self.applies(p) {
+            return Ok(());
+        }
+
+        fs::create_dir_all(&self.dir)?;
+        fs::write(self.dir.join(id), content)
+            .with_context(|| format!("Failed to save merge base for {:?}", p.display()))
+    }
+
+    /// Returns base content, but only if it's the version with the expected md5
+    pub fn load(&self, id: &str, md5: &str) -> Option<Vec<u8>> {
+        let content = fs::read(self.dir.join(id)).ok()?;
+
+        if format!("{:x}", md5::compute(&content)) != md5 {
+            return None;
+        }
+        Some(content)
+    }
+
+    pub fn remove(&self, id: &str) {
+        let _ = fs::remove_file(self.dir.join(id));
+    }
+}
+
+/// Supports `*` and `?` wildcards
+fn glob_to_regex(glob: &str) -> String {
+    let mut re = String::from("^");
+
+    for c in glob.chars() {
+        match c {
+            '*' => re.push_str(".*"),
+            '?' => re.push('.'),
+            c => re.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+
+    re.push('$');
+    re
+}
+
+/// Merges local and remote changes made to the base. Returns `None` if changes overlap
+pub fn merge(base: &str, local: &str, remote: &str) -> Option<String> {
+    let base: Vec<&str> = base.split_inclusive('\n').collect();
+    let local: Vec<&str> = local.split_inclusive('\n').collect();
+    let remote: Vec<&str> = remote.split_inclusive('\n').collect();
+
+    // Position of every base line in local and remote, if the line is kept there
+    let in_local = common_lines(&base, &local)?;
+    let in_remote = common_lines(&base, &remote)?;
+
+    let mut merged: Vec<&str> = vec![];
+    let (mut b, mut l, mut r) = (0, 0, 0);
+
+    loop {
+        // Next base line that is kept on both sides splits changes into separate chunks
+        let next = (b..base.len()).find_map(|i| match (in_local[i], in_remote[i]) {
+            (Some(li), Some(ri)) => Some((i, li, ri)),
+            _ => None,
+        });
+
+        if next == Some((b, l, r)) {
+            merged.push(base[b]);
+            b += 1;
+            l += 1;
+            r += 1;
+            continue;
+        }
+
+        let (nb, nl, nr) = next.unwrap_or((base.len(), local.len(), remote.len()));
+        let (bc, lc, rc) = (&base[b..nb], &local[l..nl], &remote[r..nr]);
+        if lc == bc {
+            merged.extend(rc);
+        } else if rc == bc || lc == rc {
+            merged.extend(lc);
+        } else {
+            return None;
+        }
+
+        if next.is_none() {
+            break;
+        }
+        b = nb;
+        l = nl;
+        r = nr;
+    }
+
+    Some(merged.concat())
+}
+
+/// Finds the longest common subsequence of lines. Returns position in `other` for every line
+/// of `base` that is a part of it, or `None` if the changed part is too big to compare
+fn common_lines(base: &[&str], other: &[&str]) -> Option<Vec<Option<usize>>> {
+    // Lines that are the same at the start and at the end are common as they are, only the
+    // changed part between them needs the table
+    let prefix = base.iter().zip(other).take_while(|(b, o)| b == o).count();
+    let suffix = base[prefix..]
+        .iter()
+        .rev()
+        .zip(other[prefix..].iter().rev())
+        .take_while(|(b, o)| b == o)
+        .count();
+    let (n, m) = (base.len() - prefix - suffix, other.len() - prefix - suffix);
+
+    if (n + 1) * (m + 1) > MAX_MERGE_CELLS {
+        return None;
+    }
+
+    let mut res: Vec<_> = (0..base.len()).map(|i| (i < prefix).then_some(i)).collect();
+    for k in 1..=suffix {
+        res[base.len() - k] = Some(other.len() - k);
+    }
+
+    let (base, other) = (&base[prefix..prefix + n], &other[prefix..prefix + m]);
+    // Length of the common subsequence for suffixes of both lists
+    let mut len = vec![vec![0u32; m + 1]; n + 1];
+
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            len[i][j] = if base[i] == other[j] {
+                len[i + 1][j + 1] + 1
+            } else {
+                len[i + 1][j].max(len[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if base[i] == other[j] {
+            res[prefix + i] = Some(prefix + j);
+            i += 1;
+            j += 1;
+        } else if len[i + 1][j] >= len[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+
+    Some(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merges_changes_of_different_lines() {
+        let base = "a\nb\nc\nd\n";
+        let local = "a\nB\nc\nd\n";
+        let remote = "a\nb\nc\nD\n";
+
+        assert_eq!(merge(base, local, remote).as_deref(), Some("a\nB\nc\nD\n"));
+    }
+
+    #[test]
+    fn keeps_same_change_of_both_sides() {
+        let base = "a\nb\n";
+        let changed = "a\nB\n";
+
+        assert_eq!(merge(base, changed, changed).as_deref(), Some(changed));
+    }
+
+    #[test]
+    fn refuses_overlapping_changes() {
+        let base = "a\nb\nc\n";
+        let local = "a\nlocal\nc\n";
+        let remote = "a\nremote\nc\n";
+
+        // No conflict markers are written, the configured strategy handles the conflict
+        assert_eq!(merge(base, local, remote), None);
+    }
+
+    #[test]
+    fn applies_deleted_lines() {
+        let base = "a\nb\nc\nd\ne\n";
+        let local = "a\nc\nd\ne\n";
+        let remote = "a\nb\nc\nd\nE\n";
+
+        assert_eq!(merge(base, local, remote).as_deref(), Some("a\nc\nd\nE\n"));
+    }
+
+    #[test]
+    fn refuses_deleting_changed_lines() {
+        let base = "a\nb\nc\n";
+        let local = "a\nc\n";
+        let remote = "a\nB\nc\n";
+
+        assert_eq!(merge(base, local, remote), None);
+    }
+
+    #[test]
+    fn merges_small_changes_of_long_files() {
+        let base = "line\n".repeat(100_000);
+        let local = format!("first\n{}", base);
+        let remote = format!("{}last\n", base);
+        let merged = format!("first\n{}last\n", base);
+
+        assert_eq!(merge(&base, &local, &remote), Some(merged));
+    }
+
+    #[test]
+    fn skips_big_changes() {
+        let base: String = (0..2000).map(|i| format!("{}\n", i)).collect();
+        let local: String = (0..2000).map(|i| format!("local {}\n", i)).collect();
+        let remote = format!("{}last\n", base);
+
+        assert_eq!(merge(&base, &local, &remote), None);
+    }
+}
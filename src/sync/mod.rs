@@ -1,7 +1,26 @@
+pub mod bandwidth;
+pub mod conflicts;
+pub mod deletions;
+pub mod ignore;
 mod local;
+pub mod merge;
+pub mod network;
+pub mod once;
+pub mod pairs;
+pub mod placeholders;
+pub mod plan;
+pub mod progress;
+pub mod queue;
+pub mod recycle_bin;
 pub mod remote;
-mod util;
-mod versions;
+pub mod root;
+pub mod selective;
+pub mod shortcuts;
+pub mod shutdown;
+pub mod state;
+pub mod transfers;
+pub mod util;
+pub mod versions;
 use crate::tray::Tray;
 use crate::{
     auth::{util::update_for_shared_client, Creds},
@@ -11,95 +30,197 @@ use crate::{
     user,
 };
 use anyhow::{bail, Result};
+use bandwidth::Bandwidth;
+use conflicts::Resolver;
+use deletions::DeletionGuard;
+use network::Network;
+use pairs::SyncPair;
+use progress::Tracker;
+use selective::Selection;
+use shutdown::Shutdown;
+use state::StateStore;
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 use std::thread;
+use tokio::{runtime::Handle, task::JoinHandle};
 use versions::Versions;
 /*
-    Setups two daemons for updates: local and remote.
+    Setups two daemons for updates: local and remote for every sync pair (main directory and
+    shared folders from the config).
     Each of them is responsible for either downloading files from the remote, or uploading local files to the remote
-    Each of daemons will be in the own thread.
-    Threads will share a mutable referce to drive client, this will allow to keep the same authroziation
-    while app is running.
+    Daemons and monitors are tasks of the runtime, only the tray has its own thread for the gtk loop.
+    Tasks share the drive client, so the authorization refreshed by one of them is used by all.
 */
-pub fn run() -> Result<()> {
-    let conf_dir = user::get_home()?.join(".config/ocean-drive");
-    let conf_file = conf_dir.join("config.toml");
-    let config = files::read_toml::<AppConfig>(conf_file)?;
+pub async fn run() -> Result<()> {
+    let ctx = load_context().await?;
 
-    let mut client = Arc::new(Mutex::new(setup_client(&conf_dir)?));
-    // Get info about root dir in the drive (We do this here because daemons will need the same
-    // info)
-    let remote_dir = get_remote_dir(&config.drive.dir, &mut client)?;
-    let versions = Arc::new(Mutex::new(Versions::new(conf_dir.join("versions.json"))?));
+    // Changes of the selective sync list take effect on start
+    {
+        let mut versions = util::lock(&ctx.versions);
+        let mut v_list = versions.list()?;
+        ctx.selection.apply(&ctx.pairs, &mut v_list)?;
+        versions.save(v_list)?;
+    }
 
-    let mut threads = vec![];
-    // Start 2 threads for remote and local daemons
-    for i in 1..=3 {
-        let cl = Arc::clone(&client);
-        let v = Arc::clone(&versions);
-        let c = config.clone();
-        let rdir_id = remote_dir.id.clone().unwrap();
-
-        let name = match i {
-            1 => "remote",
-            2 => "local",
-            _ => "tray",
+    // Daemons finish what they are doing and exit, the tray quits on its next update
+    let shutdown = Arc::clone(&ctx.shutdown);
+    tokio::spawn(async move {
+        if let Err(e) = shutdown.listen().await {
+            eprintln!("Warn: Unable to listen for stop signals: {}", e);
         }
-        .to_string();
-
-        let daemon = thread::Builder::new()
-            .name(name)
-            .spawn(move || -> Result<()> {
-                if i == 1 {
-                    let mut d =
-                        remote::RemoteDaemon::new(c.clone(), cl.clone(), v, rdir_id.clone())?;
-
-                    d.start_sync_loop()?;
-                } else if i == 2 {
-                    let d = local::LocalDaemon::new(c, cl.clone(), v, rdir_id.clone())?;
-
-                    d.start()?;
-                } else {
-                    let d = remote::RemoteDaemon::new(c.clone(), cl.clone(), v, rdir_id.clone())?;
-
-                    // TODO: Make certain path for the trayicon (e.g. in /opt)
-                    let tray = Tray::setup("./trayicon.png", d, rdir_id, c.local_dir)?;
-                    tray.start();
-                }
+    });
+
+    let mut tasks: Vec<(String, JoinHandle<Result<()>>)> = vec![];
+    let (network, shutdown) = (Arc::clone(&ctx.network), Arc::clone(&ctx.shutdown));
+    tasks.push((
+        "network".to_string(),
+        tokio::spawn(async move {
+            network.monitor(&shutdown).await;
+            Ok(())
+        }),
+    ));
+    let (bandwidth, shutdown) = (Arc::clone(&ctx.bandwidth), Arc::clone(&ctx.shutdown));
+    tasks.push((
+        "bandwidth".to_string(),
+        tokio::spawn(async move {
+            bandwidth.monitor(&shutdown).await;
+            Ok(())
+        }),
+    ));
 
-                Ok(())
-            })?;
+    // Every pair gets 2 tasks for remote and local daemons. Read-only and download-only pairs
+    // are never uploaded, so there's no need in local daemon for them. Upload-only pairs don't
+    // need remote daemon the same way
+    for pair in &ctx.pairs {
+        if pair.direction.downloads() {
+            let mut d = remote::RemoteDaemon::new(&ctx, pair.clone())?;
+            tasks.push((
+                format!("remote ({})", pair.local_dir),
+                tokio::spawn(async move { d.start_sync_loop().await }),
+            ));
+        }
 
-        threads.push(daemon);
+        if pair.read_only || !pair.direction.uploads() {
+            continue;
+        }
+
+        let d = local::LocalDaemon::new(&ctx, pair.clone(), pairs::nested_dirs(pair, &ctx.pairs))?;
+        tasks.push((
+            format!("local ({})", pair.local_dir),
+            tokio::spawn(async move { d.start().await }),
+        ));
     }
 
-    for t in threads {
-        // TODO: I hate Result<Result<...>> for t.join()
-        let name = t.thread().name().unwrap_or("no_name").to_string();
-        let started = t.join();
-        if let Err(e) = started {
-            bail!("Fatal error in a thread {:?}.\nDetails: {:#?}", name, e);
+    let remotes = ctx
+        .pairs
+        .iter()
+        .filter(|p| p.direction.downloads())
+        .map(|p| remote::RemoteDaemon::new(&ctx, p.clone()))
+        .collect::<Result<Vec<_>>>()?;
+
+    // Commands from the menu are spawned on the runtime, so the gtk loop isn't blocked by them
+    let runtime = Handle::current();
+    let tray = thread::Builder::new()
+        .name("tray".to_string())
+        .spawn(move || -> Result<()> {
+            // TODO: Make certain path for the trayicon (e.g. in /opt)
+            let tray = Tray::setup("./trayicon.png", remotes, ctx, runtime)?;
+            tray.start();
+
+            Ok(())
+        })?;
+
+    for (name, task) in tasks {
+        if let Err(e) = task.await {
+            bail!("Fatal error in a task {:?}.\nDetails: {:#?}", name, e);
         }
     }
+    if tokio::task::spawn_blocking(move || tray.join())
+        .await?
+        .is_err()
+    {
+        bail!("Fatal error in the tray thread");
+    }
 
     Ok(())
 }
 
-fn get_remote_dir(name: &String, drive_ref: &mut Arc<Mutex<Client>>) -> Result<File> {
-    let mut drive;
+/// Everything that is needed to sync: app config, authorized client, sync pairs, versions and
+/// the state shared between daemons
+#[derive(Clone)]
+pub struct Context {
+    pub config: AppConfig,
+    pub client: Arc<Client>,
+    pub remote_dir: File,
+    pub pairs: Vec<SyncPair>,
+    pub versions: Arc<Mutex<Versions>>,
+    pub state: Arc<StateStore>,
+    pub guard: Arc<DeletionGuard>,
+    pub resolver: Arc<Resolver>,
+    pub selection: Arc<Selection>,
+    pub network: Arc<Network>,
+    pub shutdown: Arc<Shutdown>,
+    pub bandwidth: Arc<Bandwidth>,
+    pub progress: Arc<Tracker>,
+}
 
-    loop {
-        if let Ok(d) = drive_ref.try_lock() {
-            drive = d;
-            break;
-        }
-    }
+pub async fn load_context() -> Result<Context> {
+    let conf_dir = user::get_home()?.join(".config/ocean-drive");
+    let conf_file = conf_dir.join("config.toml");
+    let config = files::read_toml::<AppConfig>(conf_file)?;
+
+    let client = Arc::new(setup_client(&conf_dir).await?);
+    // Get info about root dir in the drive (We do this here because daemons will need the same
+    // info)
+    let remote_dir = get_remote_dir(&config.drive.dir, &client).await?;
+    let pairs = pairs::collect(&config, &client, &remote_dir).await?;
+    let versions = Arc::new(Mutex::new(Versions::new(conf_dir.join("versions.json"))?));
+    let state = Arc::new(StateStore::new(conf_dir.join("state.json")));
+    let guard = Arc::new(DeletionGuard::new(
+        config.safeguard.clone(),
+        Arc::clone(&state),
+    ));
+    let resolver = Arc::new(Resolver::new(
+        config.conflicts.clone(),
+        Arc::clone(&state),
+        conf_dir.join("bases"),
+        config.keep_owner,
+    )?);
+    let selection = Arc::new(Selection::new(config.exclude.clone(), Arc::clone(&state))?);
+    let network = Arc::new(Network::new(Arc::clone(&state))?);
+    // Applied right away, so commands that sync once are limited too
+    let bandwidth = Arc::new(Bandwidth::new(
+        config.bandwidth.clone(),
+        Arc::clone(&client),
+    )?);
+    bandwidth.apply();
+    let progress = Arc::new(Tracker::new(Arc::clone(&state)));
+
+    Ok(Context {
+        config,
+        client,
+        remote_dir,
+        pairs,
+        versions,
+        state,
+        guard,
+        resolver,
+        selection,
+        network,
+        shutdown: Arc::new(Shutdown::new()),
+        bandwidth,
+        progress,
+    })
+}
 
-    match drive.list_files(
-        Some(&format!("name = '{}'", &name)),
-        Some("files(id, mimeType)"),
-    ) {
+async fn get_remote_dir(name: &String, drive: &Client) -> Result<File> {
+    match drive
+        .list_files(
+            Some(&format!("name = '{}'", &name)),
+            Some("files(id, mimeType)"),
+        )
+        .await
+    {
         Ok(list) => {
             if list.files.len() == 0 {
                 bail!("No file with name '{}' found in your drive", name);
@@ -120,7 +241,7 @@ fn get_remote_dir(name: &String, drive_ref: &mut Arc<Mutex<Client>>) -> Result<F
             if let Some(err) = e.downcast_ref::<DriveError>() {
                 match err {
                     DriveError::Unauthorized => {
-                        match update_for_shared_client(&mut drive) {
+                        match update_for_shared_client(drive).await {
                             Ok(_) => {
                                 println!("Info: Client authorization was updated since it was out of date.")
                             }
@@ -139,7 +260,7 @@ fn get_remote_dir(name: &String, drive_ref: &mut Arc<Mutex<Client>>) -> Result<F
     }
 }
 
-fn setup_client(conf_dir: &PathBuf) -> Result<Client> {
+pub async fn setup_client(conf_dir: &PathBuf) -> Result<Client> {
     let session_file = conf_dir.join("session.toml");
     let creds_file = conf_dir.join("creds.toml");
 
@@ -153,7 +274,7 @@ fn setup_client(conf_dir: &PathBuf) -> Result<Client> {
         Err(_) => bail!("Unable to read access authorization data.\nTip: Try to run `ocean-drive auth` to update authorization data"),
     };
 
-    let mut client = Client::new(
+    let client = Client::new(
         creds.client_id.clone(),
         creds.client_secret.clone(),
         "https://localhost:8080".to_string(),
@@ -162,7 +283,7 @@ fn setup_client(conf_dir: &PathBuf) -> Result<Client> {
     client.set_session(session.clone());
 
     if session.refresh_token.is_some() {
-        match client.refresh_token() {
+        match client.refresh_token().await {
             Ok(new_session) => {
                 files::write_toml(new_session, session_file)?;
 
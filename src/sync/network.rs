@@ -0,0 +1,87 @@
+/*
+    Connectivity monitor. The API host is probed with a lightweight request from time to time,
+    and while it's unreachable daemons are paused: the remote daemon doesn't poll the drive and
+    local changes wait in the queue. The last result is saved in the state, so
+    `ocean-drive status` can show it.
+*/
+use crate::sync::{queue::Queue, shutdown::Shutdown, state::StateStore};
+use anyhow::Result;
+use chrono::Utc;
+use reqwest::Client as HttpClient;
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+const PROBE_URL: &str = "https://www.googleapis.com/";
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+pub const PROBE_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct NetworkStatus {
+    pub online: bool,
+    /// Unix time of the last probe
+    pub checked_at: i64,
+}
+
+pub struct Network {
+    online: AtomicBool,
+    state: Arc<StateStore>,
+    http: HttpClient,
+}
+
+impl Network {
+    pub fn new(state: Arc<StateStore>) -> Result<Self> {
+        Ok(Self {
+            online: AtomicBool::new(true),
+            state,
+            http: HttpClient::builder().timeout(PROBE_TIMEOUT).build()?,
+        })
+    }
+
+    pub fn is_online(&self) -> bool {
+        self.online.load(Ordering::SeqCst)
+    }
+
+    /// Probes the API host right away. Returns true if it's reachable
+    pub async fn check(&self) -> bool {
+        // Any response means the host is reachable, even with an error status
+        let online = self.http.head(PROBE_URL).send().await.is_ok();
+
+        if self.online.swap(online, Ordering::SeqCst) != online {
+            if online {
+                println!("Info: Network is back, sync is resumed");
+                // Changes that failed while offline shouldn't wait for their backoff
+                if let Err(e) = Queue::new(Arc::clone(&self.state)).retry_now() {
+                    eprintln!("Warn: Failed to reschedule queued changes: {}", e);
+                }
+            } else {
+                eprintln!("Warn: Network is unavailable, sync is paused until it's back");
+            }
+        }
+
+        let status = NetworkStatus {
+            online,
+            checked_at: Utc::now().timestamp(),
+        };
+        if let Err(e) = self.state.update(|s| s.network = Some(status)) {
+            eprintln!("Warn: Failed to save network status: {}", e);
+        }
+
+        online
+    }
+
+    /// Probes the network until the app is stopped
+    pub async fn monitor(&self, shutdown: &Shutdown) {
+        loop {
+            self.check().await;
+            if shutdown.wait(PROBE_INTERVAL).await {
+                return;
+            }
+        }
+    }
+}
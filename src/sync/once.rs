@@ -0,0 +1,119 @@
+/*
+    One full reconciliation of every pair, for cron jobs and scripts (`ocean-drive sync --once`).
+    Remote changes go first, downloads never overwrite local changes anyway. Then the whole local
+    tree is compared with versions, so everything changed while the app wasn't running is uploaded.
+    With `dry_run` only plans are computed, neither the local tree nor the drive is changed.
+*/
+use crate::sync::{
+    local::LocalDaemon,
+    pairs::{self, Direction, SyncPair},
+    plan::Operation,
+    remote::RemoteDaemon,
+    root, util, Context,
+};
+use anyhow::Result;
+use serde::Serialize;
+
+#[derive(Default)]
+pub struct Options {
+    /// Overrides direction of every pair
+    pub direction: Option<Direction>,
+    /// Changes are only listed, nothing is synced
+    pub dry_run: bool,
+}
+
+#[derive(Serialize, Default)]
+pub struct Summary {
+    pub operations: Vec<Operation>,
+    pub errors: Vec<String>,
+    /// Conflicts waiting for the user (`ask` strategy)
+    pub conflicts: usize,
+}
+
+impl Summary {
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty() && self.conflicts == 0
+    }
+
+    /// Number of operations with the name
+    pub fn count(&self, name: &str) -> usize {
+        self.operations.iter().filter(|o| o.name() == name).count()
+    }
+}
+
+pub async fn run(ctx: &Context, opts: &Options) -> Result<Summary> {
+    let mut summary = Summary::default();
+
+    if !opts.dry_run {
+        let mut versions = util::lock(&ctx.versions);
+        let mut v_list = versions.list()?;
+        ctx.selection.apply(&ctx.pairs, &mut v_list)?;
+        versions.save(v_list)?;
+    }
+
+    for pair in &ctx.pairs {
+        let mut pair = pair.clone();
+        if let Some(direction) = opts.direction {
+            pair.direction = direction;
+        }
+
+        let checked = if opts.dry_run {
+            root::check_dry(&pair, &ctx.state)
+        } else {
+            root::check(&pair, &ctx.state)
+        };
+        if let Err(e) = checked {
+            summary.errors.push(format!(
+                "{:?} is skipped: {}\nTip: if the directory was changed on purpose, run `ocean-drive root accept`",
+                pair.local_dir, e
+            ));
+            continue;
+        }
+
+        if pair.direction.downloads() {
+            if let Err(e) = download(ctx, &pair, opts.dry_run, &mut summary).await {
+                summary.errors.push(format!(
+                    "Failed to get updates for {:?}: {}",
+                    pair.local_dir, e
+                ));
+            }
+        }
+
+        if !pair.read_only && pair.direction.uploads() {
+            let d = LocalDaemon::new(ctx, pair.clone(), pairs::nested_dirs(&pair, &ctx.pairs))?;
+            match d.scan(!opts.dry_run).await {
+                Ok((ops, errors)) => {
+                    summary.operations.extend(ops);
+                    summary.errors.extend(errors);
+                }
+                Err(e) => summary
+                    .errors
+                    .push(format!("Failed to upload {:?}: {}", pair.local_dir, e)),
+            }
+        }
+    }
+
+    summary.conflicts = ctx.state.load()?.conflicts.len();
+    Ok(summary)
+}
+
+async fn download(
+    ctx: &Context,
+    pair: &SyncPair,
+    dry_run: bool,
+    summary: &mut Summary,
+) -> Result<()> {
+    let d = RemoteDaemon::new(ctx, pair.clone())?;
+    if pair.direction == Direction::DownloadOnly && !dry_run {
+        d.revert_local_changes().await?;
+    }
+
+    // Sync is not finished only when the authorization was out of date
+    let ops = match d.sync_planned(dry_run).await? {
+        Some(ops) => ops,
+        None => d.sync_planned(dry_run).await?.unwrap_or_default(),
+    };
+    summary.operations.extend(ops);
+
+    Ok(())
+}
@@ -0,0 +1,158 @@
+/*
+    Sync pair is a local directory that is kept up to date with some folder in the drive.
+    The main pair is built from `local_dir` and `drive.dir` settings, every folder from
+    "Shared with me" listed in the config makes one more pair.
+    Every pair syncs both ways by default, `direction` of the pair limits it to one side.
+*/
+use crate::{
+    google_drive::{types::File, Client},
+    setup::{Config, SharedConfig},
+};
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// Directory inside of the main local dir that holds shared folders without own local dir
+pub const SHARED_DIR_NAME: &str = "Shared with me";
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum Direction {
+    #[default]
+    TwoWay,
+    /// Mirror of the drive: local changes are never uploaded and get reverted
+    DownloadOnly,
+    /// Backup to the drive: remote changes never touch local files
+    UploadOnly,
+}
+
+impl Direction {
+    pub fn downloads(self) -> bool {
+        self != Direction::UploadOnly
+    }
+
+    pub fn uploads(self) -> bool {
+        self != Direction::DownloadOnly
+    }
+}
+
+impl FromStr for Direction {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "two-way" => Ok(Direction::TwoWay),
+            "download-only" => Ok(Direction::DownloadOnly),
+            "upload-only" => Ok(Direction::UploadOnly),
+            _ => bail!("Unknown direction {:?}", s),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SyncPair {
+    /// Id of the remote folder. It's also used to tell pairs apart
+    pub id: String,
+    pub local_dir: String,
+    /// Items that current user can't edit are only downloaded and never uploaded
+    pub read_only: bool,
+    pub direction: Direction,
+}
+
+/// Builds the list of pairs, the main one is always the first
+pub async fn collect(
+    config: &Config,
+    client: &Client,
+    remote_root: &File,
+) -> Result<Vec<SyncPair>> {
+    let mut pairs = vec![SyncPair {
+        id: remote_root.id.clone().unwrap(),
+        local_dir: config.local_dir.clone(),
+        read_only: false,
+        direction: config.direction,
+    }];
+
+    for shared in &config.shared {
+        let folder = match client.get_file(&shared.id).await? {
+            Some(f) => f,
+            None => {
+                eprintln!(
+                    "Warn: Shared folder {:?} is not avaliable anymore. Skipping it",
+                    shared.name
+                );
+                continue;
+            }
+        };
+
+        if folder.mime_type.as_deref() != Some("application/vnd.google-apps.folder") {
+            bail!("Shared item {:?} is not a directory", shared.name);
+        }
+
+        let local_dir = shared_local_dir(config, shared);
+
+        if !Path::new(&local_dir).exists() {
+            fs::create_dir_all(&local_dir)?;
+        }
+
+        pairs.push(SyncPair {
+            id: shared.id.clone(),
+            local_dir,
+            read_only: !can_edit(&folder),
+            direction: shared.direction.unwrap_or(config.direction),
+        });
+    }
+
+    Ok(pairs)
+}
+
+/// Local dirs of every pair from the config. Unlike `collect` it does not need the drive
+pub fn local_dirs(config: &Config) -> Vec<String> {
+    let mut dirs = vec![config.local_dir.clone()];
+    for shared in &config.shared {
+        dirs.push(shared_local_dir(config, shared));
+    }
+
+    dirs
+}
+
+/// Shared folders go into `<local_dir>/Shared with me` unless other directory is set
+pub fn shared_local_dir(config: &Config, shared: &SharedConfig) -> String {
+    match &shared.local_dir {
+        Some(dir) => dir.clone(),
+        None => Path::new(&config.local_dir)
+            .join(SHARED_DIR_NAME)
+            .join(&shared.name)
+            .display()
+            .to_string(),
+    }
+}
+
+/// Directory that holds the shared folders without own local dir. It belongs to the app, so the
+/// main pair doesn't sync it. `None` if every shared folder has its own local dir
+pub fn shared_container(config: &Config) -> Option<PathBuf> {
+    if config.shared.iter().any(|s| s.local_dir.is_none()) {
+        return Some(Path::new(&config.local_dir).join(SHARED_DIR_NAME));
+    }
+    None
+}
+
+/// Local dirs of the other pairs that live inside of the `pair` local dir.
+/// Those should not be touched by the daemons of `pair`
+pub fn nested_dirs(pair: &SyncPair, pairs: &[SyncPair]) -> Vec<String> {
+    pairs
+        .iter()
+        .filter(|p| p.id != pair.id && Path::new(&p.local_dir).starts_with(&pair.local_dir))
+        .map(|p| p.local_dir.clone())
+        .collect()
+}
+
+pub fn can_edit(f: &File) -> bool {
+    f.capabilities
+        .as_ref()
+        .and_then(|c| c.can_edit)
+        .unwrap_or(true)
+}
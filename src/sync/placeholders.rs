@@ -0,0 +1,88 @@
+/*
+    Online-only files. For paths listed in `placeholders.cloud_only` of the config, the remote
+    daemon writes a small read-only placeholder with the id, size and md5 of the file instead of
+    downloading its content. Placeholders are never uploaded.
+    Content is fetched with `ocean-drive hydrate` (and evicted with `ocean-drive dehydrate`),
+    hydrated files stay local. Paths listed in `placeholders.pinned` are always downloaded.
+*/
+use crate::{google_drive::types::File, sync::util};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path};
+
+/// First line of every placeholder
+const MAGIC: &str = "ocean-drive placeholder";
+/// Placeholders are tiny, so bigger files are not even read
+const MAX_SIZE: u64 = 4096;
+
+#[derive(Deserialize, Serialize, Clone, Default)]
+pub struct PlaceholdersConfig {
+    /// Paths relative to the synced folder (e.g. "Videos") that are not downloaded
+    #[serde(default)]
+    pub cloud_only: Vec<String>,
+    /// Paths that are always downloaded, even inside of cloud-only folders
+    #[serde(default)]
+    pub pinned: Vec<String>,
+}
+
+impl PlaceholdersConfig {
+    /// `rel` is the path relative to the root of the sync pair
+    pub fn is_cloud_only(&self, rel: &Path) -> bool {
+        !self.is_pinned(rel) && self.cloud_only.iter().any(|p| matches(p, rel))
+    }
+
+    pub fn is_pinned(&self, rel: &Path) -> bool {
+        self.pinned.iter().any(|p| matches(p, rel))
+    }
+}
+
+fn matches(entry: &str, rel: &Path) -> bool {
+    rel.starts_with(entry.trim_matches('/'))
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct Placeholder {
+    /// Id of the file in the drive
+    pub id: String,
+    pub size: u64,
+    pub md5: Option<String>,
+}
+
+impl Placeholder {
+    pub fn from_file(f: &File) -> Self {
+        Self {
+            id: f.id.clone().unwrap(),
+            size: f.size.as_ref().and_then(|s| s.parse().ok()).unwrap_or(0),
+            md5: f.md5.clone(),
+        }
+    }
+}
+
+/// Returns the placeholder if the file is one
+pub fn read(p: &Path) -> Option<Placeholder> {
+    match fs::symlink_metadata(p) {
+        Ok(m) if m.is_file() && m.len() <= MAX_SIZE => {}
+        _ => return None,
+    }
+
+    let content = fs::read_to_string(p).ok()?;
+    let mut lines = content.splitn(2, '\n');
+    if lines.next()? != MAGIC {
+        return None;
+    }
+
+    serde_json::from_str(lines.next()?).ok()
+}
+
+/// Replaces the file with the placeholder. Placeholders are read-only, so nobody edits them by
+/// mistake thinking it's the real content
+pub fn write(p: &Path, placeholder: &Placeholder) -> Result<()> {
+    util::set_readonly(p, false)?;
+    fs::write(
+        p,
+        format!("{}\n{}", MAGIC, serde_json::to_string(placeholder)?),
+    )
+    .with_context(|| format!("Failed to write placeholder {:?}", p.display()))?;
+
+    util::set_readonly(p, true)
+}
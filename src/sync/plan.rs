@@ -0,0 +1,78 @@
+/*
+    Plan of a sync: operations are computed from the drive, the local tree and versions before
+    anything is changed, and then applied one by one. `ocean-drive sync --dry-run` only prints the
+    plan.
+*/
+use serde::Serialize;
+use std::fmt;
+
+/// Where the operation changes things
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    Local,
+    Remote,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum Operation {
+    /// Local file (or directory with everything inside) is uploaded to the drive
+    Upload {
+        path: String,
+    },
+    /// Drive item is written locally (directories are created)
+    Download {
+        path: String,
+    },
+    Rename {
+        from: String,
+        to: String,
+        side: Side,
+    },
+    Delete {
+        path: String,
+        side: Side,
+    },
+    /// Both sides were changed, the configured strategy decides what happens
+    Conflict {
+        path: String,
+    },
+}
+
+impl Operation {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Operation::Upload { .. } => "upload",
+            Operation::Download { .. } => "download",
+            Operation::Rename { .. } => "rename",
+            Operation::Delete { .. } => "delete",
+            Operation::Conflict { .. } => "conflict",
+        }
+    }
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operation::Upload { path } => write!(f, "upload           {}", path),
+            Operation::Download { path } => write!(f, "download         {}", path),
+            Operation::Rename { from, to, side } => {
+                write!(f, "rename {:<9} {} -> {}", side.to_string(), from, to)
+            }
+            Operation::Delete { path, side } => {
+                write!(f, "delete {:<9} {}", side.to_string(), path)
+            }
+            Operation::Conflict { path } => write!(f, "conflict         {}", path),
+        }
+    }
+}
+
+impl fmt::Display for Side {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Side::Local => write!(f, "local"),
+            Side::Remote => write!(f, "remote"),
+        }
+    }
+}
@@ -0,0 +1,278 @@
+/*
+    Progress of running transfers. Transfer workers report bytes of every file, totals with rates
+    and ETA are shown in the tray and in the log. `ocean-drive status` runs in another process, so
+    the progress is also saved in the state from time to time.
+*/
+use crate::{google_drive::Progress, sync::state::StateStore};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fmt,
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    time::{Duration, Instant},
+};
+
+/// Smaller files are done too fast to report them in the log
+const LOG_MIN_SIZE: u64 = 4 * 1024 * 1024;
+const LOG_INTERVAL: Duration = Duration::from_secs(10);
+/// How often the progress is saved in the state
+const SAVE_INTERVAL: Duration = Duration::from_secs(2);
+/// Saved progress that wasn't updated for this long is left from a stopped app
+const STALE_AFTER: i64 = 30;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    Upload,
+    Download,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FileProgress {
+    pub path: String,
+    pub kind: Kind,
+    /// Bytes transferred so far
+    pub done: u64,
+    pub total: u64,
+    /// Bytes per second since the start of the transfer
+    pub rate: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ProgressStatus {
+    pub files: Vec<FileProgress>,
+    /// Unix time of the update
+    pub updated_at: i64,
+}
+
+struct Active {
+    path: String,
+    kind: Kind,
+    total: u64,
+    done: u64,
+    started: Instant,
+    logged: Instant,
+}
+
+pub struct Tracker {
+    active: Mutex<HashMap<u64, Active>>,
+    next_id: AtomicU64,
+    state: Arc<StateStore>,
+    saved: Mutex<Instant>,
+}
+
+/// Transfer that is tracked until it's dropped
+pub struct Tracked {
+    tracker: Arc<Tracker>,
+    id: u64,
+}
+
+impl Tracker {
+    pub fn new(state: Arc<StateStore>) -> Self {
+        Self {
+            active: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(0),
+            state,
+            saved: Mutex::new(Instant::now()),
+        }
+    }
+
+    pub fn start(self: &Arc<Self>, path: &Path, kind: Kind, total: u64) -> Tracked {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        self.lock().insert(
+            id,
+            Active {
+                path: path.display().to_string(),
+                kind,
+                total,
+                done: 0,
+                started: Instant::now(),
+                logged: Instant::now(),
+            },
+        );
+
+        Tracked {
+            tracker: Arc::clone(self),
+            id,
+        }
+    }
+
+    pub fn status(&self) -> ProgressStatus {
+        let mut files: Vec<_> = self.lock().values().map(Active::progress).collect();
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+
+        ProgressStatus {
+            files,
+            updated_at: Utc::now().timestamp(),
+        }
+    }
+
+    fn update(&self, id: u64, done: u64) {
+        let log = match self.lock().get_mut(&id) {
+            Some(a) => {
+                a.done = done;
+                if a.total >= LOG_MIN_SIZE && a.logged.elapsed() >= LOG_INTERVAL {
+                    a.logged = Instant::now();
+                    Some(a.progress())
+                } else {
+                    None
+                }
+            }
+            None => return,
+        };
+
+        if let Some(p) = log {
+            println!("Info: {}", p);
+        }
+        self.save(false);
+    }
+
+    fn finish(&self, id: u64) {
+        let mut active = self.lock();
+        active.remove(&id);
+        // Saved right away when everything is done, so the status doesn't show finished files
+        let idle = active.is_empty();
+        drop(active);
+
+        self.save(idle);
+    }
+
+    fn save(&self, force: bool) {
+        {
+            let mut saved = self.saved.lock().unwrap_or_else(|e| e.into_inner());
+            if !force && saved.elapsed() < SAVE_INTERVAL {
+                return;
+            }
+            *saved = Instant::now();
+        }
+
+        let status = self.status();
+        if let Err(e) = self.state.update(|s| s.transfers = Some(status)) {
+            eprintln!("Warn: Failed to save progress of transfers: {}", e);
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<u64, Active>> {
+        self.active.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Tracked {
+    /// Callback for the drive client
+    pub fn progress(&self) -> Progress {
+        let (tracker, id) = (Arc::clone(&self.tracker), self.id);
+        Arc::new(move |done| tracker.update(id, done))
+    }
+}
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        self.tracker.finish(self.id);
+    }
+}
+
+impl Active {
+    fn progress(&self) -> FileProgress {
+        let secs = self.started.elapsed().as_secs_f64();
+
+        FileProgress {
+            path: self.path.clone(),
+            kind: self.kind,
+            done: self.done,
+            total: self.total,
+            rate: if secs > 0.0 {
+                (self.done as f64 / secs) as u64
+            } else {
+                0
+            },
+        }
+    }
+}
+
+impl ProgressStatus {
+    /// Saved progress is shown only while the app is running
+    pub fn is_current(&self) -> bool {
+        Utc::now().timestamp() - self.updated_at <= STALE_AFTER
+    }
+
+    /// One line like "2 upload(s), 1 download(s): 12.0 MiB of 40.0 MiB, 1.2 MiB/s, 25s left"
+    pub fn summary(&self) -> String {
+        let count = |kind| self.files.iter().filter(|f| f.kind == kind).count();
+        let done = self.files.iter().map(|f| f.done).sum();
+        let total = self.files.iter().map(|f| f.total).sum();
+        let rate = self.files.iter().map(|f| f.rate).sum();
+
+        format!(
+            "{} upload(s), {} download(s): {}",
+            count(Kind::Upload),
+            count(Kind::Download),
+            amounts(done, total, rate)
+        )
+    }
+}
+
+impl fmt::Display for FileProgress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = match self.kind {
+            Kind::Upload => "Uploading",
+            Kind::Download => "Downloading",
+        };
+        write!(
+            f,
+            "{} {}: {}",
+            kind,
+            self.path,
+            amounts(self.done, self.total, self.rate)
+        )
+    }
+}
+
+/// Seconds left at the current rate
+fn eta(done: u64, total: u64, rate: u64) -> Option<u64> {
+    if rate == 0 {
+        return None;
+    }
+    Some(total.saturating_sub(done) / rate)
+}
+
+fn amounts(done: u64, total: u64, rate: u64) -> String {
+    let left = match eta(done, total, rate) {
+        Some(secs) => format!(", {} left", duration(secs)),
+        None => String::new(),
+    };
+    format!(
+        "{} of {}, {}/s{}",
+        size(done),
+        size(total),
+        size(rate),
+        left
+    )
+}
+
+fn size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+fn duration(secs: u64) -> String {
+    match secs {
+        s if s < 60 => format!("{}s", s),
+        s if s < 3600 => format!("{}m {}s", s / 60, s % 60),
+        s => format!("{}h {}m", s / 3600, s % 3600 / 60),
+    }
+}
@@ -0,0 +1,168 @@
+/*
+    Durable queue of local changes. Every change caught by the local daemon is saved in the state
+    before it's sent to the drive and is removed once it's done, so changes are not lost when the
+    network is down (or the app is stopped). Failed changes are retried with a growing delay.
+*/
+use crate::sync::state::StateStore;
+use anyhow::Result;
+use chrono::Utc;
+use notify::DebouncedEvent;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashSet, fmt, path::Path, sync::Arc};
+
+/// Delay before the first retry, it's doubled after every failed attempt
+const MIN_DELAY: i64 = 10;
+const MAX_DELAY: i64 = 60 * 60;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum QueuedOp {
+    /// New or changed file or directory
+    Write {
+        path: String,
+    },
+    Rename {
+        from: String,
+        to: String,
+    },
+    Delete {
+        path: String,
+    },
+}
+
+impl QueuedOp {
+    pub fn from_event(e: &DebouncedEvent) -> Option<Self> {
+        let path = |p: &Path| p.display().to_string();
+
+        match e {
+            // Permissions are synced too, so chmod is handled like a write
+            DebouncedEvent::Create(p) | DebouncedEvent::Write(p) | DebouncedEvent::Chmod(p) => {
+                Some(QueuedOp::Write { path: path(p) })
+            }
+            DebouncedEvent::Rename(from, to) => Some(QueuedOp::Rename {
+                from: path(from),
+                to: path(to),
+            }),
+            DebouncedEvent::Remove(p) => Some(QueuedOp::Delete { path: path(p) }),
+            _ => None,
+        }
+    }
+
+    /// Path the operation is about (the new one for renames)
+    pub fn path(&self) -> &str {
+        match self {
+            QueuedOp::Write { path } | QueuedOp::Delete { path } => path,
+            QueuedOp::Rename { to, .. } => to,
+        }
+    }
+
+    /// Every path the change touches, the old path of a rename too
+    pub fn paths(&self) -> Vec<&str> {
+        match self {
+            QueuedOp::Write { path } | QueuedOp::Delete { path } => vec![path],
+            QueuedOp::Rename { from, to } => vec![from, to],
+        }
+    }
+}
+
+impl fmt::Display for QueuedOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            QueuedOp::Write { path } => write!(f, "write   {}", path),
+            QueuedOp::Rename { from, to } => write!(f, "rename  {} -> {}", from, to),
+            QueuedOp::Delete { path } => write!(f, "delete  {}", path),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct QueuedItem {
+    #[serde(flatten)]
+    pub op: QueuedOp,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    /// Unix time after which the operation is tried again
+    pub retry_at: i64,
+}
+
+pub struct Queue {
+    state: Arc<StateStore>,
+}
+
+impl Queue {
+    pub fn new(state: Arc<StateStore>) -> Self {
+        Self { state }
+    }
+
+    /// Adds the operation unless the last one waiting for the same path is the same. Earlier ones
+    /// can't be merged with it: write, delete and write again must not end with a deletion
+    pub fn push(&self, op: QueuedOp) -> Result<()> {
+        self.state.update(|s| {
+            let last = s.queue.iter().rev().find(|i| i.op.path() == op.path());
+            if last.map(|i| &i.op) != Some(&op) {
+                s.queue.push(QueuedItem {
+                    op,
+                    attempts: 0,
+                    last_error: None,
+                    retry_at: 0,
+                });
+            }
+        })
+    }
+
+    /// Operations that should be tried now, in the order they were made. `owns` picks
+    /// operations of the daemon. Operations wait for earlier ones of the same path
+    pub fn due(&self, owns: impl Fn(&Path) -> bool) -> Result<Vec<QueuedOp>> {
+        let now = Utc::now().timestamp();
+        let mut waiting = HashSet::new();
+        let mut due = vec![];
+
+        for i in self.state.load()?.queue {
+            if !owns(Path::new(i.op.path())) {
+                continue;
+            }
+            if i.retry_at > now || waiting.contains(i.op.path()) {
+                waiting.insert(i.op.path().to_string());
+                continue;
+            }
+            due.push(i.op);
+        }
+
+        Ok(due)
+    }
+
+    /// Removes the first queued copy of the operation, the same one could be queued again later
+    pub fn done(&self, op: &QueuedOp) -> Result<()> {
+        self.state.update(|s| {
+            if let Some(pos) = s.queue.iter().position(|i| &i.op == op) {
+                s.queue.remove(pos);
+            }
+        })
+    }
+
+    pub fn failed(&self, op: &QueuedOp, e: &anyhow::Error) -> Result<()> {
+        let now = Utc::now().timestamp();
+
+        self.state.update(|s| {
+            if let Some(item) = s.queue.iter_mut().find(|i| &i.op == op) {
+                let delay = MIN_DELAY
+                    .saturating_mul(1 << item.attempts.min(20))
+                    .min(MAX_DELAY);
+
+                item.attempts += 1;
+                item.last_error = Some(e.to_string());
+                item.retry_at = now + delay;
+            }
+        })
+    }
+
+    /// Makes every operation due now
+    pub fn retry_now(&self) -> Result<()> {
+        self.state
+            .update(|s| s.queue.iter_mut().for_each(|i| i.retry_at = 0))
+    }
+
+    pub fn pending(&self) -> Result<Vec<QueuedItem>> {
+        Ok(self.state.load()?.queue)
+    }
+}
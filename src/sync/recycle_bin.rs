@@ -0,0 +1,247 @@
+/*
+    Local recycle bin. Files that are removed or overwritten because of remote changes are kept in
+    `<local_dir>/.ocean-trash` for a while, so nothing is lost if the change was a mistake.
+    Every entry lives in its own directory named by the entry id, `index.json` keeps the original
+    paths.
+*/
+use crate::sync::util;
+use anyhow::{bail, Result};
+use chrono::{Local, TimeZone};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+pub const DIR_NAME: &str = ".ocean-trash";
+
+/// Files are trashed by parallel downloads, so changes of the index are made one at a time
+static INDEX_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct RecycleBinConfig {
+    /// Entries older than this are purged. `0` keeps them forever
+    #[serde(default = "default_max_age_days")]
+    pub max_age_days: u64,
+    /// The oldest entries are purged when the bin grows bigger than this. `0` means no limit
+    #[serde(default = "default_max_size_mb")]
+    pub max_size_mb: u64,
+}
+
+fn default_max_age_days() -> u64 {
+    30
+}
+
+fn default_max_size_mb() -> u64 {
+    1024
+}
+
+impl Default for RecycleBinConfig {
+    fn default() -> Self {
+        Self {
+            max_age_days: default_max_age_days(),
+            max_size_mb: default_max_size_mb(),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct Entry {
+    pub id: String,
+    pub original_path: String,
+    /// Unix timestamp
+    pub trashed_at: i64,
+    pub size: u64,
+}
+
+impl Entry {
+    pub fn trashed_at_display(&self) -> String {
+        Local
+            .timestamp(self.trashed_at, 0)
+            .format("%d.%m.%y %H:%M:%S")
+            .to_string()
+    }
+}
+
+#[derive(Clone)]
+pub struct RecycleBin {
+    dir: PathBuf,
+    config: RecycleBinConfig,
+}
+
+impl RecycleBin {
+    pub fn new(local_dir: &str, config: RecycleBinConfig) -> Self {
+        Self {
+            dir: Path::new(local_dir).join(DIR_NAME),
+            config,
+        }
+    }
+
+    /// Moves the file or the directory into the bin
+    pub fn put(&self, p: &Path) -> Result<()> {
+        let entry = self.new_entry(p)?;
+
+        if let Err(e) = fs::rename(p, self.entry_path(&entry)) {
+            fs::remove_dir(self.dir.join(&entry.id))?;
+            bail!("Unable to move {:?} to the local trash: {}", p.display(), e);
+        }
+        self.add(entry)
+    }
+
+    /// Keeps a copy of the file that is going to be overwritten
+    pub fn put_copy(&self, p: &Path) -> Result<()> {
+        let entry = self.new_entry(p)?;
+
+        if let Err(e) = fs::copy(p, self.entry_path(&entry)) {
+            fs::remove_dir_all(self.dir.join(&entry.id))?;
+            bail!("Unable to copy {:?} to the local trash: {}", p.display(), e);
+        }
+        self.add(entry)
+    }
+
+    pub fn list(&self) -> Result<Vec<Entry>> {
+        let index = self.dir.join("index.json");
+        if !index.exists() {
+            return Ok(vec![]);
+        }
+
+        let content = fs::read_to_string(&index)?;
+        Ok(serde_json::from_str(&content).unwrap_or_default())
+    }
+
+    /// Moves the entry back to its original path. If the path is taken, " (restored)" is added to
+    /// the name
+    pub fn restore(&self, id: &str) -> Result<PathBuf> {
+        let _guard = util::lock(&INDEX_LOCK);
+        let mut entries = self.list()?;
+        let entry = match entries.iter().position(|e| e.id == id) {
+            Some(i) => entries.remove(i),
+            None => bail!("No entry {:?} in the local trash", id),
+        };
+
+        let mut target = PathBuf::from(&entry.original_path);
+        if fs::symlink_metadata(&target).is_ok() {
+            let stem = target.file_stem().unwrap_or_default().to_string_lossy();
+            let name = match target.extension() {
+                Some(ext) => format!("{} (restored).{}", stem, ext.to_string_lossy()),
+                None => format!("{} (restored)", stem),
+            };
+            target.set_file_name(name);
+        }
+
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::rename(self.entry_path(&entry), &target)?;
+        fs::remove_dir(self.dir.join(&entry.id))?;
+        self.save(&entries)?;
+
+        Ok(target)
+    }
+
+    /// Removes entries that are out of the retention limits or every entry if `all` is set.
+    /// Returns count of removed entries
+    pub fn purge(&self, all: bool) -> Result<usize> {
+        let _guard = util::lock(&INDEX_LOCK);
+        self.purge_locked(all, None)
+    }
+
+    /// Entry with the `keep` id is never removed, only older ones make room for it
+    fn purge_locked(&self, all: bool, keep: Option<&str>) -> Result<usize> {
+        let mut entries = self.list()?;
+        let count = entries.len();
+        let now = Local::now().timestamp();
+        let max_age = self.config.max_age_days as i64 * 24 * 60 * 60;
+        let max_size = self.config.max_size_mb * 1024 * 1024;
+
+        // Oldest entries go first
+        entries.sort_by_key(|e| e.trashed_at);
+        let mut total: u64 = entries.iter().map(|e| e.size).sum();
+
+        let mut kept = vec![];
+        for e in entries {
+            let expired = self.config.max_age_days != 0 && now - e.trashed_at > max_age;
+            let too_big = self.config.max_size_mb != 0 && total > max_size;
+
+            if keep != Some(e.id.as_str()) && (all || expired || too_big) {
+                total -= e.size;
+                fs::remove_dir_all(self.dir.join(&e.id))?;
+                continue;
+            }
+
+            kept.push(e);
+        }
+
+        let removed = count - kept.len();
+        if removed > 0 {
+            self.save(&kept)?;
+        }
+
+        Ok(removed)
+    }
+
+    fn new_entry(&self, p: &Path) -> Result<Entry> {
+        let now = Local::now();
+        let mut id = now.format("%Y%m%d-%H%M%S%.3f").to_string();
+        fs::create_dir_all(&self.dir)?;
+
+        // Several files may be removed during the same millisecond. Creating the dir fails if
+        // it's taken, so two threads never get the same id
+        let mut i = 1;
+        loop {
+            match fs::create_dir(self.dir.join(&id)) {
+                Ok(_) => break,
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                    id = format!("{}-{}", now.format("%Y%m%d-%H%M%S%.3f"), i);
+                    i += 1;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Ok(Entry {
+            id,
+            original_path: p.display().to_string(),
+            trashed_at: now.timestamp(),
+            size: size_of(p),
+        })
+    }
+
+    fn entry_path(&self, e: &Entry) -> PathBuf {
+        let name = Path::new(&e.original_path)
+            .file_name()
+            .map(|n| n.to_os_string())
+            .unwrap_or_else(|| "unnamed".into());
+
+        self.dir.join(&e.id).join(name)
+    }
+
+    fn add(&self, entry: Entry) -> Result<()> {
+        let _guard = util::lock(&INDEX_LOCK);
+        let mut entries = self.list()?;
+        let id = entry.id.clone();
+        entries.push(entry);
+        self.save(&entries)?;
+
+        // Otherwise a file bigger than the limit would be gone right after it's trashed
+        self.purge_locked(false, Some(&id))?;
+        Ok(())
+    }
+
+    fn save(&self, entries: &[Entry]) -> Result<()> {
+        fs::write(self.dir.join("index.json"), serde_json::to_string(entries)?)?;
+        Ok(())
+    }
+}
+
+fn size_of(p: &Path) -> u64 {
+    match fs::symlink_metadata(p) {
+        Ok(m) if m.is_dir() => fs::read_dir(p)
+            .map(|entries| entries.flatten().map(|e| size_of(&e.path())).sum())
+            .unwrap_or(0),
+        Ok(m) => m.len(),
+        Err(_) => 0,
+    }
+}
@@ -2,79 +2,214 @@
     from remote to local
 */
 use crate::auth;
+use crate::files;
 use crate::google_drive::{errors::DriveError, types::File, Client};
 use crate::setup::Config;
-use crate::sync::util;
-use crate::sync::versions::{Version, Versions};
+use crate::sync::conflicts::{self, Change, Conflict, Resolution, Resolver};
+use crate::sync::deletions::{Deletion, DeletionGuard, Side};
+use crate::sync::ignore::IgnoreRules;
+use crate::sync::network::Network;
+use crate::sync::pairs::{self, Direction, SyncPair};
+use crate::sync::placeholders::{self, Placeholder};
+use crate::sync::plan::{self, Operation};
+use crate::sync::progress::{Kind, Tracker};
+use crate::sync::recycle_bin::RecycleBin;
+use crate::sync::root::RootCheck;
+use crate::sync::selective::{Excluded, Selection};
+use crate::sync::shortcuts::{self, SHORTCUT_MIME};
+use crate::sync::shutdown::Shutdown;
+use crate::sync::transfers;
+use crate::sync::versions::{ShortcutTarget, Version, Versions, VersionsList};
+use crate::sync::{util, Context};
 use anyhow::{bail, Result};
+use futures::future::BoxFuture;
 use std::{
     collections::HashMap,
     fs,
     io::Write,
     path::{Path, PathBuf},
-    str::FromStr,
-    sync::{Arc, Mutex, MutexGuard},
+    sync::{Arc, Mutex},
 };
 
+/// Change of a single drive item, found before anything is changed
+struct Step {
+    file: File,
+    parent_id: String,
+    /// Where the item should be locally
+    path: PathBuf,
+    /// Last synced version
+    local: Option<Version>,
+    kind: StepKind,
+}
+
+enum StepKind {
+    /// Synced item was moved along with its directory, only the path of the version is updated
+    Relocate,
+    /// Folder is not synced (selective sync)
+    Exclude,
+    Delete,
+    Shortcut,
+    /// Directory is created or renamed
+    Folder,
+    /// Version of the directory is saved after all of its children are synced
+    Record,
+    File(Content),
+}
+
+enum Content {
+    /// Only the name or location of the file is changed
+    Same,
+    Download,
+    /// Local changes of download-only pair are replaced
+    Revert,
+    Conflict(Conflict),
+}
+
+impl Step {
+    /// Operations of the plan made by the step. Steps that only keep versions up to date make
+    /// none
+    fn operations(&self) -> Vec<Operation> {
+        let path = self.path.display().to_string();
+        let renamed = match &self.local {
+            Some(l) if l.path != path => Some(Operation::Rename {
+                from: l.path.clone(),
+                to: path.clone(),
+                side: plan::Side::Local,
+            }),
+            _ => None,
+        };
+
+        match &self.kind {
+            StepKind::Delete => vec![Operation::Delete {
+                path: self.local.as_ref().unwrap().path.clone(),
+                side: plan::Side::Local,
+            }],
+            StepKind::Folder if self.local.is_none() => vec![Operation::Download { path }],
+            StepKind::Folder => renamed.into_iter().collect(),
+            StepKind::File(content) => {
+                let mut ops = renamed.into_iter().collect::<Vec<_>>();
+                match content {
+                    Content::Same => {}
+                    Content::Conflict(_) => ops.push(Operation::Conflict { path }),
+                    Content::Download | Content::Revert => ops.push(Operation::Download { path }),
+                }
+                ops
+            }
+            _ => vec![],
+        }
+    }
+}
+
 #[derive(Clone)]
+/// File content that's fetched by the transfer workers after the steps are applied
+struct Download {
+    file: File,
+    parent_id: String,
+    path: PathBuf,
+    read_only: bool,
+}
+
 pub struct RemoteDaemon {
-    client_ref: Arc<Mutex<Client>>,
+    client_ref: Arc<Client>,
     config: Config,
-    remote_dir_id: String,
+    pair: SyncPair,
     versions_ref: Arc<Mutex<Versions>>,
+    recycle_bin: RecycleBin,
+    guard: Arc<DeletionGuard>,
+    root_check: Arc<RootCheck>,
+    resolver: Arc<Resolver>,
+    ignore: Arc<IgnoreRules>,
+    selection: Arc<Selection>,
+    /// Directories of other sync pairs inside of the root
+    nested: Vec<String>,
+    network: Arc<Network>,
+    shutdown: Arc<Shutdown>,
+    progress: Arc<Tracker>,
 }
 
 impl RemoteDaemon {
-    pub fn new(
-        config: Config,
-        client_ref: Arc<Mutex<Client>>,
-        versions_ref: Arc<Mutex<Versions>>,
-        remote_dir_id: String,
-    ) -> Result<Self> {
+    pub fn new(ctx: &Context, pair: SyncPair) -> Result<Self> {
         Ok(Self {
-            versions_ref,
-            client_ref,
-            config,
-            remote_dir_id,
+            recycle_bin: RecycleBin::new(&pair.local_dir, ctx.config.recycle_bin.clone()),
+            versions_ref: Arc::clone(&ctx.versions),
+            client_ref: Arc::clone(&ctx.client),
+            guard: Arc::clone(&ctx.guard),
+            root_check: Arc::new(RootCheck::new(pair.clone(), Arc::clone(&ctx.state))),
+            resolver: Arc::clone(&ctx.resolver),
+            ignore: Arc::new(IgnoreRules::new(&pair.local_dir, &ctx.config.ignore)?),
+            selection: Arc::clone(&ctx.selection),
+            nested: pairs::nested_dirs(&pair, &ctx.pairs),
+            network: Arc::clone(&ctx.network),
+            shutdown: Arc::clone(&ctx.shutdown),
+            progress: Arc::clone(&ctx.progress),
+            config: ctx.config.clone(),
+            pair,
         })
     }
 
-    pub fn start_sync_loop(&mut self) -> Result<()> {
-        loop {
-            match self.sync() {
-                Ok(success) => {
-                    if !success { continue }
-                },
-                Err(e) => bail!(e),
+    pub async fn start_sync_loop(&mut self) -> Result<()> {
+        // Changes made while the app wasn't running are never uploaded for mirrors
+        if self.pair.direction == Direction::DownloadOnly && self.root_check.passes("Remote") {
+            if let Err(e) = self.revert_local_changes().await {
+                eprintln!(
+                    "Warn: Failed to revert local changes in {:?}: {}",
+                    self.pair.local_dir, e
+                );
             }
-            std::thread::sleep(std::time::Duration::from_secs(10));
         }
+
+        while !self.shutdown.is_stopped() {
+            // The drive isn't polled while offline, the monitor resumes it
+            if self.network.is_online() {
+                match self.sync().await {
+                    Ok(success) => {
+                        if !success { continue }
+                    },
+                    // Sync that was interrupted by the stop is continued on the next start
+                    Err(_) if self.shutdown.is_stopped() => break,
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        // Most of errors here are caused by the network going down
+                        self.network.check().await;
+                    }
+                }
+            }
+            self.shutdown.wait(std::time::Duration::from_secs(10)).await;
+        }
+
+        Ok(())
     }
 
     /// Returns wether process was succseffull of there was some issues that was handled, but
     /// synchronization wasn't finished
-    pub fn sync(&self) -> Result<bool> {
-        let mut client = util::lock_ref_when_free(&self.client_ref);
-        let mut versions = util::lock_ref_when_free(&self.versions_ref);
-        let mut versions_list = versions.list().unwrap();
-
-        match self.sync_dir(
-            &self.remote_dir_id,
-            PathBuf::from_str(&self.config.local_dir).unwrap(),
-            &client,
-            &mut versions_list,
-        ) {
-            Ok(_) => {}
+    pub async fn sync(&self) -> Result<bool> {
+        self.sync_planned(false).await.map(|ops| ops.is_some())
+    }
+
+    /// Computes the plan of the sync and applies it, unless `dry_run` is set. Returns operations
+    /// of the plan, or `None` if the sync should be started again
+    pub async fn sync_planned(&self, dry_run: bool) -> Result<Option<Vec<Operation>>> {
+        // Wrong local root looks like everything was deleted locally, so don't touch it at all.
+        // Dry runs are checked by the caller, since the check marks new roots
+        if !dry_run && !self.root_check.passes("Remote") {
+            return Ok(Some(vec![]));
+        }
+
+        let client = &*self.client_ref;
+        // Listing the drive and downloads take most of the time, so the sync works on a copy of
+        // versions and the local daemon isn't blocked meanwhile
+        let snapshot = Versions::snapshot(&self.versions_ref)?;
+
+        let ops = match self.sync_snapshot(client, &snapshot, dry_run).await {
+            Ok(ops) => ops,
             Err(e) => {
                 if let Some(err) = e.downcast_ref::<DriveError>() {
                     match err {
                         DriveError::Unauthorized => {
-                            match auth::util::update_for_shared_client(&mut client) {
+                            match auth::util::update_for_shared_client(client).await {
                                 Ok(_) => {
                                     println!("Info: Client authorization was updated since it was out of date.");
-                                    drop(client);
-                                    drop(versions);
-                                    return Ok(false);
+                                    return Ok(None);
                                 }
                                 Err(err) => bail!(err),
                             }
@@ -85,151 +220,570 @@ impl RemoteDaemon {
 
                 bail!("Unable to get updates from remote.\nDetails: {}", e);
             }
-        }
+        };
 
-        versions.save(versions_list).unwrap();
-        // Make shared references avaliable again
-        drop(versions);
-        drop(client);
-
-        Ok(true)
+        Ok(Some(ops))
     }
 
-    fn sync_dir(
+    /// Plans the sync with versions from the `snapshot` and applies the plan, unless `dry_run` is
+    /// set. Steps are not applied once the app is stopped or a step failed, what's done so far is
+    /// saved before the error is returned
+    async fn sync_snapshot(
         &self,
-        id: &String,
-        dir_path: PathBuf,
-        client: &MutexGuard<Client>,
-        local_versions: &mut HashMap<String, Version>,
-    ) -> Result<()> {
-        let dir_info = client.get_file(&id)?;
-
-        if dir_info.is_none() {
-            println!(
-                "Warn: Unable to find directory with id '{}' in your drive. Skipping it",
-                &id
-            );
-            return Ok(());
+        client: &Client,
+        snapshot: &VersionsList,
+        dry_run: bool,
+    ) -> Result<Vec<Operation>> {
+        let steps = self.plan(client, snapshot).await?;
+        let ops = steps.iter().flat_map(Step::operations).collect();
+        if dry_run {
+            return Ok(ops);
         }
 
-        let dir_info = dir_info.unwrap();
-        let local_dir_info = local_versions.get(id);
+        let mut versions_list = snapshot.clone();
+        let mut downloads = vec![];
+        let mut failed = None;
+        for step in steps {
+            // Directories are recorded after their children, so the ones that are not finished
+            // are listed again on the next sync
+            if self.shutdown.is_stopped() {
+                break;
+            }
+            if let Err(e) = self
+                .apply(step, client, &mut versions_list, &mut downloads)
+                .await
+            {
+                failed = Some(e);
+                break;
+            }
+        }
+        if let Err(e) = self.fetch(downloads, client, &mut versions_list).await {
+            failed.get_or_insert(e);
+        }
+        if failed.is_none() && !self.shutdown.is_stopped() {
+            shortcuts::resolve_all(
+                self.config.shortcuts,
+                client,
+                &self.pair.local_dir,
+                &mut versions_list,
+            )
+            .await;
+        }
 
-        // if the dir wasnt updated, then there's no need to even check this dir
-        if local_dir_info.is_some() && local_dir_info.unwrap().version == dir_info.version.unwrap()
-        {
-            return Ok(());
+        // Items uploaded by the local daemon meanwhile keep their new versions
+        Versions::commit(&self.versions_ref, snapshot, versions_list)?;
+        match failed {
+            Some(e) => Err(e),
+            None => Ok(ops),
         }
+    }
 
-        let dir = client.list_files(Some(&format!("'{}' in parents", &id)), None)?;
-
-        // Files is a haspmap with key of file id and value is file
-        let mut files: HashMap<String, File> = HashMap::new();
-        dir.files.iter().for_each(|f| {
-            files.insert(f.id.as_ref().unwrap().clone(), f.clone());
-        });
-
-        for (file_id, file) in files.clone() {
-            let is_folder =
-                file.mime_type.as_ref().unwrap() == "application/vnd.google-apps.folder";
-            let v = local_versions.clone();
-            let local = v.get(&file_id);
-
-            if local.is_some() {
-                let local_path = Path::new(&local.unwrap().path);
-
-                if !local_path.starts_with(&dir_path) {
-                    let updated_path = dir_path.join(file.name.as_ref().unwrap());
-                    let mut updated_version = local.unwrap().clone();
-                    updated_version.path = updated_path.into_os_string().into_string().unwrap();
-                    local_versions.remove(&file_id);
-                    local_versions.insert(file_id.clone(), updated_version);
+    /// Walks the drive tree and finds what should be changed locally. Nothing is changed here
+    async fn plan(&self, client: &Client, v_list: &VersionsList) -> Result<Vec<Step>> {
+        let mut steps = vec![];
+        self.plan_dir(
+            &self.pair.id,
+            Path::new(&self.pair.local_dir),
+            client,
+            v_list,
+            &mut steps,
+        )
+        .await?;
+
+        Ok(steps)
+    }
+
+    fn plan_dir<'a>(
+        &'a self,
+        id: &'a str,
+        dir_path: &'a Path,
+        client: &'a Client,
+        v_list: &'a VersionsList,
+        steps: &'a mut Vec<Step>,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            // Half-listed tree would look like some of its items were removed
+            if self.shutdown.is_stopped() {
+                bail!("Sync is stopped");
+            }
+
+            let dir_info = match client.get_file(id).await? {
+                Some(d) => d,
+                None => {
+                    println!(
+                        "Warn: Unable to find directory with id '{}' in your drive. Skipping it",
+                        id
+                    );
+                    return Ok(());
                 }
+            };
+
+            // if the dir wasnt updated, then there's no need to even check this dir
+            if v_list.get(id).map(|v| &v.version) == dir_info.version.as_ref() {
+                return Ok(());
             }
 
-            // This file is new or changed
-            if local.is_none() || &local.unwrap().version != file.version.as_ref().unwrap() {
-                let name = &file.name.as_ref().unwrap();
-                let f = dir_path.join(name).to_path_buf();
-                let file_path = f.to_str().unwrap();
+            let dir = client
+                .list_files(Some(&format!("'{}' in parents", id)), None)
+                .await?;
 
-                if file.trashed.unwrap() {
-                    local_versions.remove(&file_id);
-                    self.remove_from_fs(&local)?;
+            for file in dir.files {
+                let file_id = file.id.clone().unwrap();
+                let is_folder =
+                    file.mime_type.as_ref().unwrap() == "application/vnd.google-apps.folder";
+                let name = file.name.clone().unwrap();
+                let path = dir_path.join(&name);
+                let local = v_list.get(&file_id).cloned();
+
+                if self.ignore.is_ignored(&path, is_folder) {
                     continue;
                 }
 
-                if name.contains("/") {
-                    return Ok(());
+                let step = |kind| Step {
+                    file: file.clone(),
+                    parent_id: id.to_string(),
+                    path: path.clone(),
+                    local: local.clone(),
+                    kind,
+                };
+
+                if is_folder
+                    && self
+                        .selection
+                        .is_excluded(&file_id, path.strip_prefix(&self.pair.local_dir)?)
+                {
+                    steps.push(step(StepKind::Exclude));
+                    continue;
                 }
 
-                // If changed we need to update existing one. We need to remove existing for it
-                if is_folder {
-                    // Check directory name was changed, then just rename in on the file system
-                    if let Some(local) = local {
-                        if &local.path != file_path {
-                            match fs::rename(&local.path, file_path) {
-                                Err(e) => bail!(
-                                    "Failed to rename file {:?} to {:?}: {}",
-                                    local.path,
-                                    file_path,
-                                    e
-                                ),
-                                Ok(_) => {}
-                            }
+                // Not changed items of a renamed directory are moved along with it
+                if let Some(local) = &local {
+                    if &local.version == file.version.as_ref().unwrap() {
+                        if !Path::new(&local.path).starts_with(dir_path) {
+                            steps.push(step(StepKind::Relocate));
                         }
+                        continue;
                     }
+                }
 
-                    // Generate a path for a subdirectory
-                    let subdir = dir_path.join(name);
-                    if !subdir.exists() {
-                        fs::create_dir(subdir.clone())?;
+                if file.trashed.unwrap() {
+                    if local.is_some() {
+                        steps.push(step(StepKind::Delete));
                     }
+                    continue;
+                }
+
+                // Such names can't be used locally
+                if name.contains('/') {
+                    continue;
+                }
+
+                // Shortcuts are only recorded here, they are resolved when the whole tree is synced
+                if file.mime_type.as_deref() == Some(SHORTCUT_MIME) {
+                    steps.push(step(StepKind::Shortcut));
+                    continue;
+                }
+
+                // Directory is created before its children and recorded after them, so it's checked
+                // again if some of them fail
+                if is_folder {
+                    steps.push(step(StepKind::Folder));
+                    self.plan_dir(&file_id, &path, client, v_list, steps)
+                        .await?;
+                    steps.push(step(StepKind::Record));
+                    continue;
+                }
 
-                    // We go recursively for every file in the subdir
-                    self.sync_dir(&file_id, subdir, client, local_versions)?;
+                // Only the name or the location is changed when the content is the same
+                let content = if local.as_ref().map(|l| &l.md5) == Some(&file.md5) {
+                    Content::Same
                 } else {
-                    // Check if it's a new file and download it
-                    // Also re-download if we the file data has changed
-                    if local.is_none() || local.unwrap().md5 != file.md5 {
-                        let filepath = dir_path.join(&name);
-                        self.save_file(client, &file, filepath)?;
+                    match self.local_change(&file, id, &path, local.as_ref())? {
+                        Some(c) => c,
+                        None => continue,
                     }
+                };
+                steps.push(step(StepKind::File(content)));
+            }
 
-                    // If the file is present, we check if it's was renamed
-                    if let Some(local) = local {
-                        if &local.path != file_path {
-                            fs::rename(&local.path, &file_path)?;
+            Ok(())
+        })
+    }
+
+    /// Checks that downloading the file won't overwrite local changes that weren't uploaded yet
+    /// (made while offline or right before the sync, so the local daemon didn't get them).
+    /// Returns `None` if the file should not be downloaded
+    fn local_change(
+        &self,
+        file: &File,
+        parent_id: &str,
+        file_path: &Path,
+        local: Option<&Version>,
+    ) -> Result<Option<Content>> {
+        // Synced file is still at the old path if it was renamed in the drive
+        let path = match local {
+            Some(local) => PathBuf::from(&local.path),
+            None => file_path.to_path_buf(),
+        };
+        // Placeholders have no content to lose
+        if !path.is_file() || placeholders::read(&path).is_some() {
+            return Ok(Some(Content::Download));
+        }
+
+        let base = local.and_then(|l| l.md5.clone());
+        let hash = format!("{:x}", md5::compute(files::read_bytes(path.clone())?));
+
+        match conflicts::detect(base.as_ref(), Some(&hash), file.md5.as_ref()) {
+            Change::None | Change::Remote => return Ok(Some(Content::Download)),
+            // Local content is never uploaded for mirrors, it can only be replaced
+            Change::Local | Change::Both if self.pair.direction == Direction::DownloadOnly => {
+                return Ok(Some(Content::Revert))
+            }
+            // Only local content was changed, it's uploaded by the local daemon
+            Change::Local => return Ok(None),
+            Change::Both => {}
+        }
+
+        Ok(Some(Content::Conflict(Conflict {
+            id: file.id.clone().unwrap(),
+            path: path.display().to_string(),
+            parent_id: parent_id.to_string(),
+            base_md5: base,
+            local_md5: hash,
+            remote_md5: file.md5.clone(),
+            remote_modified: file.modified_time.clone(),
+            read_only: self.pair.read_only || !pairs::can_edit(file),
+        })))
+    }
+
+    /// Makes the change of the step locally and records the new version. Contents of files are
+    /// added to `downloads` instead, their versions are recorded once they are fetched
+    async fn apply(
+        &self,
+        step: Step,
+        client: &Client,
+        local_versions: &mut VersionsList,
+        downloads: &mut Vec<Download>,
+    ) -> Result<()> {
+        let Step {
+            file,
+            parent_id,
+            path,
+            local,
+            kind,
+        } = step;
+        let file_id = file.id.clone().unwrap();
+        let is_folder = file.mime_type.as_ref().unwrap() == "application/vnd.google-apps.folder";
+        let read_only = self.pair.read_only || !pairs::can_edit(&file);
+        let file_path = path.display().to_string();
+
+        match kind {
+            StepKind::Relocate => {
+                if let Some(v) = local_versions.get_mut(&file_id) {
+                    v.path = file_path;
+                }
+                return Ok(());
+            }
+            StepKind::Exclude => {
+                return self.selection.mark(Excluded {
+                    id: file_id,
+                    parent_id,
+                    path: file_path,
+                });
+            }
+            StepKind::Delete => {
+                let local = local.unwrap();
+                let deletion = Deletion {
+                    id: file_id.clone(),
+                    side: Side::Remote,
+                    root: self.pair.local_dir.clone(),
+                    version: local.clone(),
+                };
+
+                // Held deletions keep their versions until the user decides what to do
+                if !self.guard.allow(deletion, local_versions.len())? {
+                    return Ok(());
+                }
+
+                local_versions.remove(&file_id);
+                self.resolver.bases().remove(&file_id);
+                return self.remove_from_fs(&Some(&local));
+            }
+            StepKind::Shortcut => {
+                if let Some(local) = &local {
+                    if local.shortcut.is_none() || local.path != file_path {
+                        self.remove_from_fs(&Some(local))?;
+                    }
+                }
+
+                let target = file.shortcut_details.as_ref().unwrap().target_id.clone();
+                local_versions.insert(
+                    file_id,
+                    Version {
+                        is_folder: false,
+                        md5: None,
+                        parent_id,
+                        path: file_path,
+                        version: file.version.unwrap(),
+                        read_only: true,
+                        shortcut: Some(ShortcutTarget {
+                            id: target,
+                            state: None,
+                            unresolved: false,
+                        }),
+                        mode: None,
+                    },
+                );
+                return Ok(());
+            }
+            StepKind::Folder => {
+                // Check directory name was changed, then just rename in on the file system
+                if let Some(local) = &local {
+                    if local.path != file_path {
+                        if let Err(e) = fs::rename(&local.path, &path) {
+                            bail!(
+                                "Failed to rename file {:?} to {:?}: {}",
+                                local.path,
+                                file_path,
+                                e
+                            );
+                        }
+                    }
+                }
+
+                if !path.exists() {
+                    fs::create_dir(&path)?;
+                }
+                return Ok(());
+            }
+            StepKind::Record => {}
+            StepKind::File(content) => {
+                let download = match content {
+                    Content::Same => false,
+                    Content::Download => true,
+                    Content::Revert => {
+                        eprintln!("Warn: Local changes of {:?} are reverted since the folder is download-only. Changed content is in the local trash", file_path);
+                        true
+                    }
+                    Content::Conflict(c) => {
+                        let res = self.resolver.resolve(&c, client, local_versions).await?;
+                        if res != Resolution::KeepRemote && res != Resolution::KeepBoth {
+                            return Ok(());
                         }
+                        true
                     }
+                };
+
+                // Renamed before the download, so the new content takes the new path
+                if let Some(local) = &local {
+                    if local.path != file_path {
+                        fs::rename(&local.path, &path)?;
+                    }
+                }
+
+                // Permissions could be changed in the drive without the content
+                let is_real = path.is_file() && placeholders::read(&path).is_none();
+                let mode_changed =
+                    file.mode().is_some() && file.mode() != local.as_ref().and_then(|l| l.mode);
+                if !download && is_real && mode_changed {
+                    util::restore_permissions(&path, &file, self.config.keep_owner)?;
+                    util::set_readonly(&path, read_only)?;
                 }
 
-                // If local version is present, we need to remove it before updating
-                if local.is_some() {
-                    local_versions.remove(&file_id);
+                if download {
+                    if self.is_cloud_only(&path) {
+                        placeholders::write(&path, &Placeholder::from_file(&file))?;
+                    } else {
+                        downloads.push(Download {
+                            file,
+                            parent_id,
+                            path,
+                            read_only,
+                        });
+                        return Ok(());
+                    }
                 }
+            }
+        }
 
-                let latest = Version {
-                    is_folder,
+        // Files that got no permissions from the drive keep the local ones
+        let mode = file.mode().or_else(|| util::local_mode(&path));
+        local_versions.insert(
+            file_id,
+            Version {
+                is_folder,
+                md5: file.md5,
+                parent_id,
+                path: file_path,
+                version: file.version.unwrap(),
+                read_only,
+                shortcut: None,
+                mode: mode.filter(|_| !is_folder),
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Downloads files on the transfer workers and records versions of the downloaded ones
+    async fn fetch(
+        &self,
+        downloads: Vec<Download>,
+        client: &Client,
+        local_versions: &mut VersionsList,
+    ) -> Result<()> {
+        let mut pending: HashMap<_, _> = downloads
+            .iter()
+            .map(|d| (d.file.id.clone(), d.parent_id.clone()))
+            .collect();
+        let jobs = downloads
+            .into_iter()
+            .map(|d| {
+                let size = d.file.size.as_ref().and_then(|s| s.parse().ok());
+                (size.unwrap_or(0), d)
+            })
+            .collect();
+        let results = transfers::run(
+            self.config.transfers.downloads,
+            jobs,
+            &self.shutdown,
+            |d| async move {
+                let res = self
+                    .save_file(client, &d.file, d.path.clone(), d.read_only)
+                    .await;
+                (d, res)
+            },
+        )
+        .await;
+
+        let mut failed = None;
+        for (d, res) in results {
+            if let Err(e) = res {
+                failed.get_or_insert(e);
+                continue;
+            }
+            pending.remove(&d.file.id);
+
+            let Download {
+                file,
+                parent_id,
+                path,
+                read_only,
+            } = d;
+            let mode = file.mode().or_else(|| util::local_mode(&path));
+            local_versions.insert(
+                file.id.unwrap(),
+                Version {
+                    is_folder: false,
                     md5: file.md5,
-                    parent_id: id.clone(),
-                    path: dir_path.join(name).into_os_string().into_string().unwrap(),
-                    version: file.version.as_ref().unwrap().to_string(),
-                };
-                local_versions.insert(file_id, latest.clone());
+                    parent_id,
+                    path: path.display().to_string(),
+                    version: file.version.unwrap(),
+                    read_only,
+                    shortcut: None,
+                    mode,
+                },
+            );
+        }
+
+        // Downloads that failed or weren't started before the stop are made on the next sync
+        for parent_id in pending.values() {
+            Versions::invalidate(parent_id, local_versions);
+        }
+
+        match failed {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    /// Downloads synced files of the pair again if they were changed or removed locally
+    pub async fn revert_local_changes(&self) -> Result<()> {
+        let client = &self.client_ref;
+        // Only local files are changed, versions stay the same
+        let v_list = Versions::snapshot(&self.versions_ref)?;
+
+        for (id, v) in &v_list {
+            if self.shutdown.is_stopped() {
+                break;
+            }
+
+            let path = Path::new(&v.path);
+            if v.is_folder
+                || v.shortcut.is_some()
+                || !path.starts_with(&self.pair.local_dir)
+                || self.nested.iter().any(|n| path.starts_with(n))
+                || placeholders::read(path).is_some()
+            {
+                continue;
+            }
+
+            if path.is_file() {
+                let hash = format!("{:x}", md5::compute(files::read_bytes(path.to_path_buf())?));
+                if v.md5.as_ref() == Some(&hash) {
+                    continue;
+                }
+            }
+
+            // Removed folders are restored when their contents are
+            if let Some(file) = client
+                .get_file(id)
+                .await?
+                .filter(|f| !f.trashed.unwrap_or(false))
+            {
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                eprintln!(
+                    "Warn: Local changes of {:?} are reverted since the folder is download-only",
+                    path.display()
+                );
+                self.save_file(client, &file, path.to_path_buf(), v.read_only)
+                    .await?;
             }
         }
 
         Ok(())
     }
 
-    fn save_file(
+    /// Files that were hydrated stay local, even in cloud-only folders
+    fn is_cloud_only(&self, file_path: &Path) -> bool {
+        let rel = match file_path.strip_prefix(&self.pair.local_dir) {
+            Ok(rel) => rel,
+            Err(_) => return false,
+        };
+
+        self.config.placeholders.is_cloud_only(rel)
+            && (!file_path.exists() || placeholders::read(file_path).is_some())
+    }
+
+    async fn save_file(
         &self,
-        client: &MutexGuard<Client>,
+        client: &Client,
         file: &File,
         file_path: PathBuf,
+        read_only: bool,
     ) -> Result<()> {
-        let contents = client.download_file(file.id.as_ref().unwrap()).unwrap();
+        let id = file.id.as_ref().unwrap();
+        let size = file.size.as_ref().and_then(|s| s.parse().ok());
+        let tracked = self
+            .progress
+            .start(&file_path, Kind::Download, size.unwrap_or(0));
+        let contents = client
+            .download_with_progress(id, tracked.progress())
+            .await?;
+        drop(tracked);
+
+        // Keep the old content in the local trash, unless it's the same as the new one
+        if file_path.is_file() && placeholders::read(&file_path).is_none() {
+            let hash = format!("{:x}", md5::compute(files::read_bytes(file_path.clone())?));
+            if Some(&hash) != file.md5.as_ref() {
+                self.recycle_bin.put_copy(&file_path)?;
+            }
+        }
+
+        // Read-only files should become writable for a moment to get the new content
+        util::set_readonly(&file_path, false)?;
 
         match fs::OpenOptions::new()
             .create(true)
@@ -237,12 +791,16 @@ impl RemoteDaemon {
             .truncate(true)
             .open(&file_path)
         {
-            Ok(mut file) => {
-                if let Err(e) = file.write(&contents) {
+            Ok(mut local) => {
+                if let Err(e) = local.write(&contents) {
                     bail!("Error writing to file {:?}: {}", file_path.display(), e)
                 }
 
-                Ok(())
+                // Same time as in the drive, so the file doesn't look changed after the download
+                util::set_modified(&local, file.modified_time.as_deref())?;
+                util::restore_permissions(&file_path, file, self.config.keep_owner)?;
+                self.resolver.bases().save(id, &file_path, &contents)?;
+                util::set_readonly(&file_path, read_only)
             }
             Err(e) => bail!(
                 "Unable to access file {:?}: {}",
@@ -252,13 +810,17 @@ impl RemoteDaemon {
         }
     }
 
-    /* Removes a file from a local root, the opposite of save_file fn */
+    /* Removes a file from a local root, the opposite of save_file fn.
+    Removed files go to the local trash, except for things created for shortcuts */
     fn remove_from_fs(&self, local: &Option<&Version>) -> Result<()> {
         if let Some(local) = local {
             let removed_path = Path::new(&local.path);
 
-            if removed_path.exists() {
-                if local.is_folder {
+            // `exists` follows symlinks, so a broken one would be left behind
+            if fs::symlink_metadata(&removed_path).is_ok() {
+                if local.shortcut.is_none() && placeholders::read(removed_path).is_none() {
+                    self.recycle_bin.put(removed_path)?;
+                } else if local.is_folder {
                     fs::remove_dir_all(&removed_path)?;
                 } else {
                     fs::remove_file(&removed_path)?;
@@ -0,0 +1,124 @@
+/*
+    Protection from syncing a wrong local root. If the local dir lives on an external disk that is
+    not mounted yet, daemons would see an empty tree and delete everything in the drive.
+    So every local dir gets a marker file with the id of its pair, and the device of the dir is
+    remembered in the state. Sync is suspended while any of those don't match.
+*/
+use crate::sync::{pairs::SyncPair, state::StateStore};
+use anyhow::{bail, Result};
+use std::{
+    fs,
+    os::unix::fs::MetadataExt,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+pub const MARKER_NAME: &str = ".ocean-drive";
+
+/// Returns an error with the reason if the pair should not be synced
+pub fn check(pair: &SyncPair, state: &StateStore) -> Result<()> {
+    verify(pair, state, true)
+}
+
+/// Same as `check`, but a dir that was never synced is not marked
+pub fn check_dry(pair: &SyncPair, state: &StateStore) -> Result<()> {
+    verify(pair, state, false)
+}
+
+fn verify(pair: &SyncPair, state: &StateStore, mark_new: bool) -> Result<()> {
+    let dir = Path::new(&pair.local_dir);
+    let device = match fs::metadata(dir) {
+        Ok(m) if m.is_dir() => m.dev(),
+        _ => bail!("directory {:?} does not exist", pair.local_dir),
+    };
+
+    let marker = dir.join(MARKER_NAME);
+    let known_device = state.load()?.roots.get(&pair.id).cloned();
+
+    if !marker.exists() {
+        // Nothing was synced here yet, so it's fine to start from scratch
+        if known_device.is_none() {
+            return if mark_new {
+                accept(pair, state)
+            } else {
+                Ok(())
+            };
+        }
+
+        bail!(
+            "marker file {:?} is missing. Perhaps, the directory is not mounted",
+            marker.display()
+        );
+    }
+
+    if fs::read_to_string(&marker)?.trim() != pair.id {
+        bail!(
+            "marker file {:?} belongs to another folder",
+            marker.display()
+        );
+    }
+
+    match known_device {
+        Some(d) if d != device => bail!("directory is on a different device than before"),
+        Some(_) => Ok(()),
+        None if mark_new => accept(pair, state),
+        None => Ok(()),
+    }
+}
+
+/// Marks the current local dir as the right one for the pair
+pub fn accept(pair: &SyncPair, state: &StateStore) -> Result<()> {
+    let dir = Path::new(&pair.local_dir);
+    let device = fs::metadata(dir)?.dev();
+
+    fs::write(dir.join(MARKER_NAME), &pair.id)?;
+    state.update(|s| {
+        s.roots.insert(pair.id.clone(), device);
+    })?;
+
+    Ok(())
+}
+
+/// Periodic check of the local root for a daemon. Warns only when the result changes
+pub struct RootCheck {
+    pair: SyncPair,
+    state: Arc<StateStore>,
+    suspended: AtomicBool,
+}
+
+impl RootCheck {
+    pub fn new(pair: SyncPair, state: Arc<StateStore>) -> Self {
+        Self {
+            pair,
+            state,
+            suspended: AtomicBool::new(false),
+        }
+    }
+
+    /// `daemon` is the name used in messages
+    pub fn passes(&self, daemon: &str) -> bool {
+        match check(&self.pair, &self.state) {
+            Ok(_) => {
+                if self.suspended.swap(false, Ordering::SeqCst) {
+                    println!(
+                        "Info: {} daemon of {:?} is resumed",
+                        daemon, self.pair.local_dir
+                    );
+                }
+                true
+            }
+            Err(e) => {
+                if !self.suspended.swap(true, Ordering::SeqCst) {
+                    eprintln!(
+                        "Warn: {} daemon of {:?} is suspended: {}\nTip: if the directory was changed on purpose, run `ocean-drive root accept`",
+                        daemon, self.pair.local_dir, e
+                    );
+                }
+                false
+            }
+        }
+    }
+}
@@ -0,0 +1,141 @@
+/*
+    Selective sync: remote folders listed in `exclude` of the config are never downloaded, and
+    their local copies are not uploaded back.
+    Excluded folders that the remote daemon met are remembered in the state, so the local daemon
+    knows their paths even when they are excluded by id, and they are downloaded again once they
+    are removed from the list.
+*/
+use crate::sync::{
+    pairs::SyncPair,
+    state::StateStore,
+    util,
+    versions::{Versions, VersionsList},
+};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Excluded {
+    /// Id of the folder in the drive
+    pub id: String,
+    pub parent_id: String,
+    /// Local path the folder would have
+    pub path: String,
+}
+
+pub struct Selection {
+    /// Remote paths relative to the root of the sync pair, or folder ids
+    entries: Vec<String>,
+    state: Arc<StateStore>,
+    excluded: Mutex<Vec<Excluded>>,
+}
+
+impl Selection {
+    pub fn new(entries: Vec<String>, state: Arc<StateStore>) -> Result<Self> {
+        let excluded = Mutex::new(state.load()?.excluded);
+
+        Ok(Self {
+            entries,
+            state,
+            excluded,
+        })
+    }
+
+    /// `rel` is the path of the folder relative to the root of its sync pair
+    pub fn is_excluded(&self, id: &str, rel: &Path) -> bool {
+        self.entries
+            .iter()
+            .any(|e| e == id || Path::new(e.trim_matches('/')) == rel)
+    }
+
+    /// Checks if the local path is inside of an excluded folder
+    pub fn is_excluded_path(&self, root: &Path, p: &Path) -> bool {
+        let by_path = self
+            .entries
+            .iter()
+            .any(|e| p.starts_with(root.join(e.trim_matches('/'))));
+
+        by_path
+            || util::lock(&self.excluded)
+                .iter()
+                .any(|e| p.starts_with(&e.path))
+    }
+
+    /// Remembers the folder the remote daemon skipped
+    pub fn mark(&self, e: Excluded) -> Result<()> {
+        let mut excluded = util::lock(&self.excluded);
+        if excluded.contains(&e) {
+            return Ok(());
+        }
+
+        excluded.retain(|x| x.id != e.id);
+        excluded.push(e);
+        let list = excluded.clone();
+        self.state.update(|s| s.excluded = list)
+    }
+
+    /// Brings the versions list up to date with the config. Folders that became excluded are
+    /// forgotten (local copies are kept), folders that aren't excluded anymore get their parents
+    /// rescanned, so they are downloaded on the next sync
+    pub fn apply(&self, pairs: &[SyncPair], v_list: &mut VersionsList) -> Result<()> {
+        let mut excluded = util::lock(&self.excluded);
+
+        excluded.retain(|e| match relative(pairs, &e.path) {
+            Some(rel) if self.is_excluded(&e.id, &rel) => true,
+            _ => {
+                println!("Info: {:?} is synced again", e.path);
+                Versions::invalidate(&e.parent_id, v_list);
+                false
+            }
+        });
+
+        let folders: Vec<(String, String, String)> = v_list
+            .iter()
+            .filter(|(_, v)| v.is_folder)
+            .map(|(id, v)| (id.clone(), v.parent_id.clone(), v.path.clone()))
+            .collect();
+
+        for (id, parent_id, path) in folders {
+            // Already forgotten along with an excluded folder above it
+            if !v_list.contains_key(&id) {
+                continue;
+            }
+
+            let rel = match relative(pairs, &path) {
+                Some(rel) => rel,
+                None => continue,
+            };
+            if !self.is_excluded(&id, &rel) {
+                continue;
+            }
+
+            println!(
+                "Info: {:?} is excluded from sync. Local copy is kept, but it's not synced anymore",
+                path
+            );
+            v_list.retain(|_, v| !Path::new(&v.path).starts_with(&path));
+            excluded.push(Excluded {
+                id,
+                parent_id,
+                path,
+            });
+        }
+
+        let list = excluded.clone();
+        self.state.update(|s| s.excluded = list)
+    }
+}
+
+/// Path relative to the root of the closest sync pair
+fn relative(pairs: &[SyncPair], path: &str) -> Option<PathBuf> {
+    pairs
+        .iter()
+        .filter(|p| Path::new(path).starts_with(&p.local_dir))
+        .max_by_key(|p| p.local_dir.len())
+        .and_then(|p| Path::new(path).strip_prefix(&p.local_dir).ok())
+        .map(Path::to_path_buf)
+}
@@ -0,0 +1,233 @@
+/*
+    Drive shortcuts are files that point to another file or folder.
+    Depending on the config they become symlinks to the synced path of the target, full copies
+    of the target or they are skipped at all.
+    Shortcuts are resolved after the whole tree of the pair is synced, so all targets from the
+    tree already have their local paths.
+*/
+use crate::{
+    google_drive::Client,
+    sync::{
+        util,
+        versions::{Version, VersionsList},
+    },
+};
+use anyhow::{bail, Result};
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use std::{fs, os::unix::fs as unix_fs, path::Path};
+
+pub const SHORTCUT_MIME: &str = "application/vnd.google-apps.shortcut";
+const FOLDER_MIME: &str = "application/vnd.google-apps.folder";
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ShortcutPolicy {
+    /// Symlink to the local path of the target. Targets that aren't synced are skipped
+    #[default]
+    Symlink,
+    /// Read-only copy of the target content
+    Copy,
+    Skip,
+}
+
+/// Resolves every shortcut that lives inside of `local_dir`
+pub async fn resolve_all(
+    policy: ShortcutPolicy,
+    client: &Client,
+    local_dir: &str,
+    v_list: &mut VersionsList,
+) {
+    let shortcuts: Vec<(String, Version)> = v_list
+        .iter()
+        .filter(|(_, v)| v.shortcut.is_some() && Path::new(&v.path).starts_with(local_dir))
+        .map(|(id, v)| (id.clone(), v.clone()))
+        .collect();
+
+    for (id, v) in shortcuts {
+        let mut updated = v.clone();
+
+        match resolve(policy, client, &mut updated, v_list).await {
+            Ok(_) => {
+                updated.shortcut.as_mut().unwrap().unresolved = false;
+            }
+            Err(e) => {
+                if !v.shortcut.as_ref().unwrap().unresolved {
+                    eprintln!("Warn: Shortcut {:?} is skipped: {}", v.path, e);
+                }
+                updated.shortcut.as_mut().unwrap().unresolved = true;
+            }
+        }
+
+        v_list.insert(id, updated);
+    }
+}
+
+async fn resolve(
+    policy: ShortcutPolicy,
+    client: &Client,
+    v: &mut Version,
+    v_list: &VersionsList,
+) -> Result<()> {
+    let path = Path::new(&v.path).to_path_buf();
+    let target = v.shortcut.clone().unwrap();
+
+    match policy {
+        ShortcutPolicy::Skip => {
+            if target.state.is_some() {
+                remove_local(&path)?;
+                v.shortcut.as_mut().unwrap().state = None;
+            }
+        }
+        ShortcutPolicy::Symlink => {
+            let target_path = match v_list.get(&target.id) {
+                Some(t) => t.path.clone(),
+                None => bail!("target is not synced"),
+            };
+
+            // Symlink to one of the parent dirs makes an endless tree
+            if path.starts_with(&target_path) {
+                bail!("shortcut points to its own parent directory");
+            }
+
+            if target.state.as_ref() == Some(&target_path) && is_symlink(&path) {
+                return Ok(());
+            }
+
+            prepare_path(&path, target.state.is_some())?;
+            unix_fs::symlink(&target_path, &path)?;
+
+            v.is_folder = false;
+            v.shortcut.as_mut().unwrap().state = Some(target_path);
+        }
+        ShortcutPolicy::Copy => {
+            let info = match client.get_file(&target.id).await? {
+                Some(f) if !f.trashed.unwrap_or(false) => f,
+                _ => bail!("target is not avaliable in the drive"),
+            };
+
+            // Every folder above the shortcut, the pair root is the last one
+            let mut trail = vec![];
+            let mut parent = Some(v.parent_id.clone());
+            while let Some(id) = parent {
+                parent = v_list.get(&id).map(|p| p.parent_id.clone());
+                trail.push(id);
+            }
+
+            if trail.contains(&target.id) {
+                bail!("shortcut points to its own parent directory");
+            }
+
+            if target.state == info.version && path.exists() {
+                return Ok(());
+            }
+
+            prepare_path(&path, target.state.is_some())?;
+
+            let is_folder = info.mime_type.as_deref() == Some(FOLDER_MIME);
+            if is_folder {
+                trail.push(target.id.clone());
+                copy_tree(client, &target.id, &path, &mut trail).await?;
+            } else {
+                copy_file(client, &target.id, &path).await?;
+            }
+
+            v.is_folder = is_folder;
+            v.shortcut.as_mut().unwrap().state = info.version;
+        }
+    }
+
+    Ok(())
+}
+
+/// Downloads content of the remote folder. `trail` holds ids of the folders that are being copied
+/// to detect shortcuts loops
+fn copy_tree<'a>(
+    client: &'a Client,
+    id: &'a str,
+    path: &'a Path,
+    trail: &'a mut Vec<String>,
+) -> BoxFuture<'a, Result<()>> {
+    Box::pin(async move {
+        fs::create_dir_all(path)?;
+
+        let list = client
+            .list_files(
+                Some(&format!("'{}' in parents and trashed = false", id)),
+                None,
+            )
+            .await?;
+
+        for f in list.files {
+            let name = f.name.unwrap_or_default();
+            if name.contains('/') {
+                continue;
+            }
+
+            let child_path = path.join(&name);
+            let mut child_id = f.id.unwrap();
+            let mut mime = f.mime_type.unwrap_or_default();
+
+            if mime == SHORTCUT_MIME {
+                let details = f.shortcut_details.unwrap();
+                child_id = details.target_id;
+                mime = details.target_mime_type.unwrap_or_default();
+            }
+
+            if trail.contains(&child_id) {
+                eprintln!(
+                    "Warn: Shortcut {:?} points to its own parent directory. Skipping it",
+                    child_path.display()
+                );
+                continue;
+            }
+
+            if mime == FOLDER_MIME {
+                trail.push(child_id.clone());
+                copy_tree(client, &child_id, &child_path, trail).await?;
+                trail.pop();
+            } else {
+                copy_file(client, &child_id, &child_path).await?;
+            }
+        }
+
+        Ok(())
+    })
+}
+
+async fn copy_file(client: &Client, id: &str, path: &Path) -> Result<()> {
+    let contents = client.download_file(id).await?;
+
+    fs::write(path, contents)?;
+    util::set_readonly(path, true)
+}
+
+/// Makes sure nothing is at the `path`. Only things created by the shortcut (`owned`) and symlinks
+/// can be removed
+fn prepare_path(path: &Path, owned: bool) -> Result<()> {
+    if fs::symlink_metadata(path).is_err() {
+        return Ok(());
+    }
+
+    if !owned && !is_symlink(path) {
+        bail!("{:?} already exists", path.display());
+    }
+
+    remove_local(path)
+}
+
+fn remove_local(path: &Path) -> Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(m) if m.is_dir() => fs::remove_dir_all(path)?,
+        Ok(_) => fs::remove_file(path)?,
+        Err(_) => {}
+    }
+
+    Ok(())
+}
+
+fn is_symlink(path: &Path) -> bool {
+    fs::symlink_metadata(path)
+        .map(|m| m.file_type().is_symlink())
+        .unwrap_or(false)
+}
@@ -0,0 +1,74 @@
+/*
+    Graceful shutdown of daemons. The app is stopped with Ctrl+C, SIGTERM or from the tray, and
+    daemons are woken up from their waits, so they finish the current step (and save versions)
+    instead of being killed in the middle of writing a file. Transfers that weren't started yet are
+    skipped. The second signal exits right away.
+*/
+use anyhow::Result;
+use std::time::Duration;
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    sync::watch,
+};
+
+pub struct Shutdown {
+    stopped: watch::Sender<bool>,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self {
+            stopped: watch::channel(false).0,
+        }
+    }
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn stop(&self) {
+        self.stopped.send_replace(true);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        *self.stopped.borrow()
+    }
+
+    /// Resolves once the app is stopped
+    pub async fn stopped(&self) {
+        let mut stopped = self.stopped.subscribe();
+        // The sender lives as long as `self`, so the wait can't fail
+        let _ = stopped.wait_for(|s| *s).await;
+    }
+
+    /// Sleeps for `timeout` unless the app is stopped meanwhile. Returns true if it's stopped
+    pub async fn wait(&self, timeout: Duration) -> bool {
+        let _ = tokio::time::timeout(timeout, self.stopped()).await;
+        self.is_stopped()
+    }
+
+    /// Stops the app on Ctrl+C or SIGTERM. Runs until the process exits
+    pub async fn listen(&self) -> Result<()> {
+        let mut interrupt = signal(SignalKind::interrupt())?;
+        let mut terminate = signal(SignalKind::terminate())?;
+        let mut signalled = false;
+
+        loop {
+            tokio::select! {
+                _ = interrupt.recv() => {}
+                _ = terminate.recv() => {}
+            }
+
+            if signalled {
+                eprintln!("Warn: Stopped without waiting for changes that are being synced");
+                std::process::exit(130);
+            }
+            signalled = true;
+
+            println!("Info: Stopping. Changes that are being synced right now are finished first.\nTip: press Ctrl+C again to exit right away");
+            self.stop();
+        }
+    }
+}
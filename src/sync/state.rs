@@ -0,0 +1,103 @@
+/*
+    Keeps the state of the app that should survive restarts (like deletions waiting for the user
+    confirmation) in ~/.config/ocean-drive/state.json.
+    Daemons and cli commands read the file every time, so changes made by one process are visible
+    for another.
+*/
+use crate::sync::{
+    conflicts::Conflict, deletions::Deletion, network::NetworkStatus, progress::ProgressStatus,
+    queue::QueuedItem, selective::Excluded,
+};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fs, path::PathBuf};
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct State {
+    #[serde(default)]
+    pub pending_deletions: Vec<Deletion>,
+    /// Device of the local dir for every pair id
+    #[serde(default)]
+    pub roots: HashMap<String, u64>,
+    /// Conflicts waiting for the user (`ask` strategy)
+    #[serde(default)]
+    pub conflicts: Vec<Conflict>,
+    /// Excluded folders met by remote daemons (selective sync)
+    #[serde(default)]
+    pub excluded: Vec<Excluded>,
+    /// Local changes that are not in the drive yet
+    #[serde(default)]
+    pub queue: Vec<QueuedItem>,
+    /// Last result of the connectivity monitor
+    #[serde(default)]
+    pub network: Option<NetworkStatus>,
+    /// Transfers that are running
+    #[serde(default)]
+    pub transfers: Option<ProgressStatus>,
+}
+
+pub struct StateStore {
+    path: PathBuf,
+}
+
+impl StateStore {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    pub fn load(&self) -> Result<State> {
+        let lock = self.open_lock()?;
+        lock.lock_shared()?;
+        self.read()
+    }
+
+    /// Applies changes to the state and saves it. Returns whatever `f` returns.
+    /// The file lock is held from reading to saving, so changes made by daemons and cli commands
+    /// at the same time don't overwrite each other
+    pub fn update<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut State) -> T,
+    {
+        let lock = self.open_lock()?;
+        lock.lock()?;
+        let mut state = self.read()?;
+        let res = f(&mut state);
+
+        // Readers never see a half-written file
+        let tmp = self.path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_string(&state)?)
+            .and_then(|_| fs::rename(&tmp, &self.path))
+            .with_context(|| format!("Failed to save state file {:?}", self.path.display()))?;
+
+        Ok(res)
+    }
+
+    /// The lock is separate from the state file, since the state file is replaced on every save.
+    /// It's released when the returned file is closed
+    fn open_lock(&self) -> Result<fs::File> {
+        let path = self.path.with_extension("lock");
+        fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+            .with_context(|| format!("Failed to open state lock {:?}", path.display()))
+    }
+
+    fn read(&self) -> Result<State> {
+        if !self.path.exists() {
+            return Ok(State::default());
+        }
+
+        let content = fs::read_to_string(&self.path)
+            .with_context(|| format!("Failed to read state file {:?}", self.path.display()))?;
+
+        // Falling back to the default would drop held deletions and queued changes on next save
+        serde_json::from_str(&content).with_context(|| {
+            format!(
+                "State file {:?} is corrupted. Fix or remove it to start with an empty state",
+                self.path.display()
+            )
+        })
+    }
+}
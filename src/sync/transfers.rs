@@ -0,0 +1,60 @@
+/*
+    Pool of transfers. Daemons plan and check everything that changes metadata on their own, and
+    only the contents of files are sent or fetched concurrently. Results are returned to the
+    caller, so versions are still written from a single place.
+*/
+use crate::sync::shutdown::Shutdown;
+use futures::{future, Future, StreamExt};
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct TransfersConfig {
+    /// Max count of files downloaded at once
+    #[serde(default = "default_workers")]
+    pub downloads: usize,
+    /// Max count of files uploaded at once
+    #[serde(default = "default_workers")]
+    pub uploads: usize,
+}
+
+fn default_workers() -> usize {
+    4
+}
+
+impl Default for TransfersConfig {
+    fn default() -> Self {
+        Self {
+            downloads: default_workers(),
+            uploads: default_workers(),
+        }
+    }
+}
+
+/// Runs `transfer` for every job, up to `workers` at once. Jobs come with their sizes, the
+/// smallest ones are started first so a few big files don't hold back the rest. Results are in
+/// the order of jobs.
+/// Jobs are not started once the app is stopped, so there are no results for them
+pub async fn run<J, T, F, Fut>(
+    workers: usize,
+    jobs: Vec<(u64, J)>,
+    shutdown: &Shutdown,
+    transfer: F,
+) -> Vec<T>
+where
+    F: Fn(J) -> Fut,
+    Fut: Future<Output = T>,
+{
+    let mut jobs: Vec<_> = jobs.into_iter().enumerate().collect();
+    jobs.sort_by_key(|(_, (size, _))| *size);
+
+    let transfer = &transfer;
+    let mut results: Vec<_> = futures::stream::iter(jobs)
+        .take_while(|_| future::ready(!shutdown.is_stopped()))
+        .map(|(i, (_, job))| async move { (i, transfer(job).await) })
+        .buffer_unordered(workers.max(1))
+        .collect()
+        .await;
+
+    results.sort_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, r)| r).collect()
+}
@@ -1,11 +1,105 @@
-use std::sync::{Arc, Mutex, MutexGuard};
+use crate::google_drive::types::{File, FileAttributes};
+use crate::sync::{recycle_bin, root};
+use anyhow::Result;
+use chrono::{DateTime, SecondsFormat, Utc};
+use std::{
+    collections::HashMap,
+    fs,
+    os::unix::fs::{self as unix_fs, MetadataExt, PermissionsExt},
+    path::Path,
+    sync::{Mutex, MutexGuard},
+    time::SystemTime,
+};
 
-pub fn lock_ref_when_free<T>(r: &Arc<Mutex<T>>) -> MutexGuard<T> {
-    loop {
-        if let Ok(v) = r.try_lock() {
-            return v;
+/// Waits until the shared value is free. A thread that panicked while holding it doesn't stop
+/// the others
+pub fn lock<T>(r: &Mutex<T>) -> MutexGuard<'_, T> {
+    r.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Checks if the path is one of the files the app keeps inside of the local root for itself
+pub fn is_internal(root: &Path, p: &Path) -> bool {
+    match p.strip_prefix(root).ok().and_then(|r| r.components().next()) {
+        Some(c) => c.as_os_str() == recycle_bin::DIR_NAME || c.as_os_str() == root::MARKER_NAME,
+        None => false,
+    }
+}
+
+/// Removes (or gives back) write permissions for the file
+pub fn set_readonly(p: &Path, readonly: bool) -> Result<()> {
+    if !p.exists() {
+        return Ok(());
+    }
+
+    let mut perms = fs::metadata(p)?.permissions();
+    let mode = if readonly {
+        perms.mode() & !0o222
+    } else {
+        perms.mode() | 0o200
+    };
+
+    if mode != perms.mode() {
+        perms.set_mode(mode);
+        fs::set_permissions(p, perms)?;
+    }
+
+    Ok(())
+}
+
+/// Attributes of the local file to keep them in the drive: modification and creation times
+/// (creation time isn't known on every file system), permissions and the owner if it's kept
+pub fn local_attributes(p: &Path, keep_owner: bool) -> Result<FileAttributes> {
+    let meta = fs::metadata(p)?;
+    let format =
+        |t: SystemTime| DateTime::<Utc>::from(t).to_rfc3339_opts(SecondsFormat::Millis, true);
+
+    let mut properties = HashMap::new();
+    properties.insert("mode".to_string(), format!("{:o}", meta.mode() & 0o7777));
+    if keep_owner {
+        properties.insert("uid".to_string(), meta.uid().to_string());
+        properties.insert("gid".to_string(), meta.gid().to_string());
+    }
+
+    Ok(FileAttributes {
+        modified_time: Some(format(meta.modified()?)),
+        created_time: meta.created().ok().map(format),
+        app_properties: Some(properties),
+    })
+}
+
+/// Permission bits of the local file, like `0o755`
+pub fn local_mode(p: &Path) -> Option<u32> {
+    fs::metadata(p).ok().map(|m| m.mode() & 0o7777)
+}
+
+/// Gives the local file permissions (and the owner if it's kept) saved in the drive
+pub fn restore_permissions(p: &Path, file: &File, keep_owner: bool) -> Result<()> {
+    if let Some(mode) = file.mode() {
+        if local_mode(p) != Some(mode) {
+            fs::set_permissions(p, fs::Permissions::from_mode(mode))?;
         }
+    }
+
+    if let (true, Some((uid, gid))) = (keep_owner, file.owner()) {
+        let meta = fs::metadata(p)?;
+        if (meta.uid(), meta.gid()) != (uid, gid) {
+            if let Err(e) = unix_fs::chown(p, Some(uid), Some(gid)) {
+                eprintln!(
+                    "Warn: Failed to restore the owner of {:?}: {}",
+                    p.display(),
+                    e
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
 
-        std::thread::sleep(std::time::Duration::from_secs(1));
+/// Sets the modification time of the local file to the one from the drive (RFC 3339)
+pub fn set_modified(file: &fs::File, modified_time: Option<&str>) -> Result<()> {
+    if let Some(time) = modified_time.and_then(|t| DateTime::parse_from_rfc3339(t).ok()) {
+        file.set_modified(SystemTime::from(time))?;
     }
+    Ok(())
 }
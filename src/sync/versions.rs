@@ -1,22 +1,46 @@
 /*
     This struct is used to manage versions.json file
-    It can be shared between threads and used with mutex to avoid threads trying to read/write to file simulteniosly
+    It's shared between threads behind a mutex. Daemons work on a snapshot of the list without
+    holding the guard (transfers take long), and their changes are merged into the current list
+    under the guard when they are done, so changes of other daemons made meanwhile are kept
 */
+use crate::sync::util;
 use anyhow::{bail, Result};
 use serde::{Deserialize, Serialize};
 use serde_json;
 use std::fs;
 use std::io::Write;
 use std::path::PathBuf;
+use std::sync::Mutex;
 
 /// Represents all data assosiated with file
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct Version {
     pub is_folder: bool,
     pub parent_id: String,
     pub version: String,
     pub path: String,
     pub md5: Option<String>,
+    /// Item can't be edited by the user, so it's never uploaded back
+    #[serde(default)]
+    pub read_only: bool,
+    /// Set only for drive shortcuts
+    #[serde(default)]
+    pub shortcut: Option<ShortcutTarget>,
+    /// Permission bits of the synced file, so changes made with chmod are synced too
+    #[serde(default)]
+    pub mode: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ShortcutTarget {
+    pub id: String,
+    /// Path the symlink points to or version of the copied target. `None` if nothing was created
+    /// locally yet
+    pub state: Option<String>,
+    /// Set when the shortcut can't be resolved, so the warning is shown only once
+    #[serde(default)]
+    pub unresolved: bool,
 }
 
 pub type VersionsList = std::collections::HashMap<String, Version>;
@@ -25,23 +49,37 @@ pub type VersionsItem = (String, Version);
 
 pub struct Versions {
     path: PathBuf,
-    is_locked: bool,
 }
 
 impl Versions {
     pub fn new(path: PathBuf) -> Result<Self> {
         // Check if file accessible
         match fs::OpenOptions::new().create(true).write(true).open(&path) {
-            Ok(_) => Ok(Self {
-                path,
-                is_locked: false,
-            }),
+            Ok(_) => Ok(Self { path }),
             Err(e) => {
                 bail!("Unable to access versions file, this file is required for program to work.\nDetails: {}", e);
             }
         }
     }
 
+    /// Reads the list without keeping it locked
+    pub fn snapshot(versions: &Mutex<Self>) -> Result<VersionsList> {
+        util::lock(versions).list()
+    }
+
+    /// Saves changes made to the `base` snapshot in `changed`. The current list is read again
+    /// under the lock, items changed there meanwhile keep that change
+    pub fn commit(
+        versions: &Mutex<Self>,
+        base: &VersionsList,
+        changed: VersionsList,
+    ) -> Result<()> {
+        let mut versions = util::lock(versions);
+        let mut current = versions.list()?;
+        merge(base, changed, &mut current);
+        versions.save(current)
+    }
+
     /// Finds item by path field.
     pub fn find_item_by_path(p: PathBuf, l: &VersionsList) -> Option<VersionsItem> {
         let p = p.display().to_string();
@@ -52,20 +90,26 @@ impl Versions {
         None
     }
 
-    pub fn list(&mut self) -> Result<VersionsList> {
-        loop {
-            if self.is_locked {
-                std::thread::sleep(std::time::Duration::from_secs(1));
-            }
-            break;
+    /// Resets versions of the item and every directory above it, so the remote daemon checks the
+    /// whole path again on the next sync
+    pub fn invalidate(id: &str, l: &mut VersionsList) {
+        let mut next = Some(id.to_string());
+
+        while let Some(id) = next {
+            next = match l.get_mut(&id) {
+                Some(v) => {
+                    v.version = String::new();
+                    Some(v.parent_id.clone())
+                }
+                None => None,
+            };
         }
+    }
 
+    pub fn list(&mut self) -> Result<VersionsList> {
         match fs::read_to_string(&self.path) {
             Ok(content) => match serde_json::from_str::<VersionsList>(content.as_str()) {
-                Ok(r) => {
-                    self.is_locked = true;
-                    Ok(r)
-                }
+                Ok(r) => Ok(r),
                 Err(_) => Ok(VersionsList::new()),
             },
             Err(e) => {
@@ -84,10 +128,7 @@ impl Versions {
             Ok(mut f) => {
                 let content = serde_json::to_string(&versions)?;
                 match f.write_all(content.as_bytes()) {
-                    Ok(_) => {
-                        self.is_locked = false;
-                        Ok(())
-                    }
+                    Ok(_) => Ok(()),
                     Err(e) => {
                         bail!("Failed to save versions data to file.\nDetails: {}", e);
                     }
@@ -99,3 +140,20 @@ impl Versions {
         }
     }
 }
+
+/// Applies differences between `base` and `changed` to `current`, unless the item is different
+/// in `current` too
+fn merge(base: &VersionsList, changed: VersionsList, current: &mut VersionsList) {
+    for (id, v) in base {
+        if !changed.contains_key(id) && current.get(id) == Some(v) {
+            current.remove(id);
+        }
+    }
+
+    for (id, v) in changed {
+        let old = base.get(&id);
+        if old != Some(&v) && current.get(&id) == old {
+            current.insert(id, v);
+        }
+    }
+}
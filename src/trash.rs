@@ -0,0 +1,94 @@
+/* Local trash with files that were removed or overwritten because of remote changes */
+
+use crate::{
+    setup,
+    sync::{pairs, recycle_bin::RecycleBin},
+};
+use anyhow::{bail, Result};
+use clap::{App, Arg, ArgMatches, SubCommand};
+
+pub fn root_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("trash")
+        .about("Manages local trash with files removed or overwritten by changes from the drive.")
+        .usage("ocean-drive trash [SUBCOMMAND (if needed)]")
+        .after_help("Without subcommands lists everything in the local trash")
+        .subcommand(SubCommand::with_name("list").about("Lists everything in the local trash"))
+        .subcommand(
+            SubCommand::with_name("restore")
+                .about("Moves items back to their original place")
+                .arg(
+                    Arg::with_name("ids")
+                        .required(true)
+                        .multiple(true)
+                        .help("Ids of the items (See `ocean-drive trash list`)"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("purge")
+                .about("Removes items that are older or bigger than configured limits")
+                .arg(
+                    Arg::with_name("all")
+                        .long("all")
+                        .help("Remove everything from the local trash"),
+                ),
+        )
+}
+
+pub fn run(m: &ArgMatches) -> Result<()> {
+    let config = setup::read_config()?;
+    let bins: Vec<RecycleBin> = pairs::local_dirs(&config)
+        .iter()
+        .map(|dir| RecycleBin::new(dir, config.recycle_bin.clone()))
+        .collect();
+
+    match m.subcommand() {
+        ("restore", Some(sub)) => {
+            for id in sub.values_of("ids").unwrap() {
+                let bin = bins
+                    .iter()
+                    .find(|b| b.list().unwrap_or_default().iter().any(|e| e.id == id));
+
+                match bin {
+                    Some(bin) => {
+                        let restored = bin.restore(id)?;
+                        println!("Restored {:?}", restored.display());
+                    }
+                    None => bail!("No item {:?} in the local trash", id),
+                }
+            }
+
+            Ok(())
+        }
+        ("purge", Some(sub)) => {
+            let mut removed = 0;
+            for bin in &bins {
+                removed += bin.purge(sub.is_present("all"))?;
+            }
+
+            println!("Removed {} item(s) from the local trash", removed);
+            Ok(())
+        }
+        _ => {
+            let mut empty = true;
+
+            for bin in &bins {
+                for e in bin.list()? {
+                    empty = false;
+                    println!(
+                        "{}  [{}]  {} ({} bytes)",
+                        e.id,
+                        e.trashed_at_display(),
+                        e.original_path,
+                        e.size
+                    );
+                }
+            }
+
+            if empty {
+                println!("Local trash is empty");
+            }
+
+            Ok(())
+        }
+    }
+}
@@ -1,10 +1,11 @@
 // This code is taken from https://github.com/olback/tray-item-rs/blob/master/src/api/linux/mod.rs
 // and was gently adapted for my needs
-use crate::sync::remote::RemoteDaemon;
+use crate::sync::{deletions, remote::RemoteDaemon, Context};
 use anyhow::Result;
-use gtk::prelude::*;
+use gtk::{glib, prelude::*};
 use libappindicator::{AppIndicator, AppIndicatorStatus};
-use std::process::Command;
+use std::{future::Future, process::Command, sync::Arc};
+use tokio::runtime::Handle;
 use webbrowser;
 
 pub struct Tray {
@@ -15,12 +16,15 @@ pub struct Tray {
 impl Tray {
     pub fn setup(
         icon: &str,
-        remote: RemoteDaemon,
-        remote_dir_id: String,
-        local_path: String,
+        remotes: Vec<RemoteDaemon>,
+        ctx: Context,
+        runtime: Handle,
     ) -> Result<Self> {
         gtk::init()?;
 
+        let remote_dir_id = ctx.remote_dir.id.clone().unwrap();
+        let local_path = ctx.config.local_dir.clone();
+
         let mut t = Self {
             tray: AppIndicator::new("Ocean Drive", icon),
             menu: gtk::Menu::new(),
@@ -31,8 +35,76 @@ impl Tray {
 
         t.add_label(&version).unwrap();
 
+        let connection = t.add_label("")?;
+        let transfers = t.add_label("")?;
+        let status = t.add_label("")?;
+        let (c, rt) = (ctx.clone(), runtime.clone());
+        let confirm = t.add_menu_item("Confirm deletions", move || -> Result<()> {
+            let c = c.clone();
+            spawn(&rt, async move { resolve_deletions(true, &c).await });
+            Ok(())
+        })?;
+        let (c, rt) = (ctx.clone(), runtime.clone());
+        let discard = t.add_menu_item("Discard deletions", move || -> Result<()> {
+            let c = c.clone();
+            spawn(&rt, async move { resolve_deletions(false, &c).await });
+            Ok(())
+        })?;
+
+        // Quick switch for the limits from the config, e.g. when the uplink is needed for a call
+        let bandwidth = ctx.bandwidth.clone();
+        let limit = t.add_menu_item("", move || -> Result<()> {
+            bandwidth.set_enabled(!bandwidth.is_enabled());
+            Ok(())
+        })?;
+
+        // Menu is updated from time to time, since daemons run on the runtime threads
+        let guard = ctx.guard.clone();
+        let network = ctx.network.clone();
+        let shutdown = ctx.shutdown.clone();
+        let bandwidth = ctx.bandwidth.clone();
+        let progress = ctx.progress.clone();
+        let update = move || {
+            // Daemons are stopped from the runtime too (e.g. by Ctrl+C)
+            if shutdown.is_stopped() {
+                gtk::main_quit();
+                return;
+            }
+
+            let pending = guard.pending();
+
+            connection.set_label(if network.is_online() {
+                "Online"
+            } else {
+                "Offline, sync is paused"
+            });
+
+            let running = progress.status();
+            transfers.set_label(&running.summary());
+            transfers.set_visible(!running.files.is_empty());
+
+            limit.set_label(if bandwidth.is_enabled() {
+                "Don't limit bandwidth"
+            } else {
+                "Limit bandwidth"
+            });
+            limit.set_visible(bandwidth.is_configured());
+
+            status.set_label(&format!("{} deletions pending", pending));
+            status.set_visible(pending > 0);
+            confirm.set_visible(pending > 0);
+            discard.set_visible(pending > 0);
+        };
+
+        let remotes = Arc::new(remotes);
         t.add_menu_item("Sync now", move || -> Result<()> {
-            remote.sync()?;
+            let remotes = Arc::clone(&remotes);
+            spawn(&runtime, async move {
+                for remote in remotes.iter() {
+                    remote.sync().await?;
+                }
+                Ok(())
+            });
             Ok(())
         })?;
 
@@ -64,13 +136,21 @@ impl Tray {
             Ok(())
         })?;
 
-        t.add_menu_item("Stop Ocean", || -> Result<()> {
-            gtk::main_quit();
+        let shutdown = ctx.shutdown.clone();
+        t.add_menu_item("Stop Ocean", move || -> Result<()> {
             println!("Tray: Received stop command. Exitting.");
-            std::process::exit(0);
+            shutdown.stop();
+            Ok(())
         })
         .unwrap();
 
+        // Every item is shown when added, so hide what's not needed only after the menu is built
+        update();
+        glib::timeout_add_seconds_local(2, move || {
+            update();
+            glib::Continue(true)
+        });
+
         t.set_icon(icon)?;
 
         Ok(t)
@@ -87,28 +167,55 @@ impl Tray {
         Ok(())
     }
 
-    fn add_label(&mut self, label: &str) -> Result<()> {
+    fn add_label(&mut self, label: &str) -> Result<gtk::MenuItem> {
         let item = gtk::MenuItem::with_label(label.as_ref());
         item.set_sensitive(false);
         self.menu.append(&item);
         self.menu.show_all();
         self.tray.set_menu(&mut self.menu);
 
-        Ok(())
+        Ok(item)
     }
 
-    fn add_menu_item<F>(&mut self, label: &str, cb: F) -> Result<()>
+    fn add_menu_item<F>(&mut self, label: &str, cb: F) -> Result<gtk::MenuItem>
     where
         F: Fn() -> Result<()> + Send + Sync + 'static,
     {
         let item = gtk::MenuItem::with_label(label.as_ref());
         item.connect_activate(move |_| {
-            cb().unwrap();
+            if let Err(e) = cb() {
+                eprintln!("Tray: {}", e);
+            }
         });
         self.menu.append(&item);
         self.menu.show_all();
         self.tray.set_menu(&mut self.menu);
 
-        Ok(())
+        Ok(item)
     }
 }
+
+/// Runs the command on the runtime, so the menu isn't blocked until it's done
+fn spawn<F>(runtime: &Handle, command: F)
+where
+    F: Future<Output = Result<()>> + Send + 'static,
+{
+    runtime.spawn(async move {
+        if let Err(e) = command.await {
+            eprintln!("Tray: {}", e);
+        }
+    });
+}
+
+async fn resolve_deletions(confirm: bool, ctx: &Context) -> Result<()> {
+    let client = &*ctx.client;
+    let resolved =
+        deletions::resolve(confirm, &ctx.config, client, &ctx.state, &ctx.versions).await?;
+
+    println!(
+        "Tray: {} deletion(s) {}",
+        resolved,
+        if confirm { "confirmed" } else { "discarded" }
+    );
+    Ok(())
+}